@@ -1,35 +1,137 @@
-extern crate nalgebra as na;
-use na::{Isometry3, Matrix4};
+//! Camera math for looking at the board. Nothing here touches the GPU, the
+//! viewer uploads the matrix from [`Camera::get_transform`] itself.
 
-#[derive(Debug)]
+use na::{Isometry3, Matrix4, Point3, Vector4};
+
+#[derive(Debug, Clone)]
 pub struct Camera {
     pub pos: na::Point<f32, 3>,
     pub target: na::Point<f32, 3>,
-    pub buffer: wgpu::Buffer,
     pub rot_x: f32,
     pub rot_y: f32,
 }
 
 impl Camera {
-    fn _layout(state: &crate::RenderState) -> wgpu::VertexBufferLayout {
-        todo!();
+    /// Combined projection and view matrix for a viewport with the given
+    /// aspect ratio (width / height). The camera orbits `target` as `time`
+    /// (in seconds) goes on.
+    pub fn get_transform(&self, aspect: f32, time: f32) -> Matrix4<f32> {
+        let proj = na::Perspective3::new(aspect, 90.0, 0.1, 100.0);
+
+        let view = Isometry3::look_at_rh(&self.eye(time), &self.target, &na::Vector3::y());
+
+        proj.as_matrix() * view.to_homogeneous()
+    }
+
+    /// Where the camera is at `time` as it orbits.
+    pub fn eye(&self, time: f32) -> Point3<f32> {
+        let rot = Matrix4::new_rotation_wrt_point(na::Vector3::y() * (time % 360.0), self.target)
+            .normalize()
+            * 10.0;
+
+        rot.transform_point(&self.pos)
     }
+}
+
+/// The six planes bounding what a camera sees, for skipping things that
+/// are out of view.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
 
-    pub fn get_transform(&self, state: &crate::RenderState) -> Matrix4<f32> {
-        let size = state.window.inner_size();
-        let proj = na::Perspective3::new(size.width as f32 / size.height as f32, 90.0, 0.1, 100.0);
+impl Frustum {
+    /// The view volume of a combined projection and view matrix like the
+    /// one from [`Camera::get_transform`], clipped the way wgpu does it
+    /// (depth from 0 to w).
+    pub fn from_matrix(m: &Matrix4<f32>) -> Frustum {
+        let row = |i: usize| m.row(i).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
 
-        let rot =
-            Matrix4::new_rotation_wrt_point(na::Vector3::y() * (state.time % 360.0), self.target)
-                .normalize()
-                * 10.0;
+        Frustum {
+            planes: [w + x, w - x, w + y, w - y, z, w - z],
+        }
+    }
 
+    /// False if the box from `min` to `max` is certainly out of view.
+    pub fn intersects(&self, min: Point3<f32>, max: Point3<f32>) -> bool {
+        self.planes.iter().all(|p| {
+            // the corner furthest inside this plane
+            let corner = Vector4::new(
+                if p.x >= 0.0 { max.x } else { min.x },
+                if p.y >= 0.0 { max.y } else { min.y },
+                if p.z >= 0.0 { max.z } else { min.z },
+                1.0,
+            );
+            p.dot(&corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // looking down -z at the origin from z = 10
+    fn frustum() -> Frustum {
+        let proj = na::Perspective3::new(1.0, std::f32::consts::FRAC_PI_2, 0.1, 100.0);
         let view = Isometry3::look_at_rh(
-            &rot.transform_point(&self.pos),
-            &self.target,
+            &Point3::new(0.0, 0.0, 10.0),
+            &Point3::origin(),
             &na::Vector3::y(),
         );
+        Frustum::from_matrix(&(proj.as_matrix() * view.to_homogeneous()))
+    }
 
-        proj.as_matrix() * view.to_homogeneous()
+    fn unit_box(center: Point3<f32>) -> (Point3<f32>, Point3<f32>) {
+        let half = na::Vector3::repeat(0.5);
+        (center - half, center + half)
+    }
+
+    #[test]
+    fn keeps_boxes_in_front() {
+        let frustum = frustum();
+        for center in [
+            Point3::origin(),
+            Point3::new(5.0, -5.0, 0.0),
+            Point3::new(0.0, 0.0, -80.0),
+        ] {
+            let (min, max) = unit_box(center);
+            assert!(frustum.intersects(min, max), "{}", center);
+        }
+        // straddling the edge of the view
+        assert!(frustum.intersects(Point3::new(9.0, 0.0, -0.5), Point3::new(20.0, 1.0, 0.5)));
+    }
+
+    #[test]
+    fn culls_boxes_out_of_view() {
+        let frustum = frustum();
+        for center in [
+            // behind the eye
+            Point3::new(0.0, 0.0, 15.0),
+            // off to either side
+            Point3::new(30.0, 0.0, 0.0),
+            Point3::new(0.0, -30.0, 0.0),
+            // past the far plane
+            Point3::new(0.0, 0.0, -200.0),
+        ] {
+            let (min, max) = unit_box(center);
+            assert!(!frustum.intersects(min, max), "{}", center);
+        }
+    }
+
+    #[test]
+    fn sees_the_target() {
+        let camera = Camera {
+            pos: Point3::new(0.0, 4.0, -5.0),
+            target: Point3::origin(),
+            rot_x: 0.0,
+            rot_y: 0.0,
+        };
+        for time in [0.0, 1.0, 2.5] {
+            let frustum = Frustum::from_matrix(&camera.get_transform(1.5, time));
+            let (min, max) = unit_box(camera.target);
+            assert!(frustum.intersects(min, max), "{}", time);
+        }
     }
 }
@@ -0,0 +1,182 @@
+//! When the viewer steps the board: pausing, speed and queued steps.
+
+use std::time::{Duration, Instant};
+
+/// Turns elapsed frame time into generations to step.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    pub paused: bool,
+    /// Generations per second when not at max speed.
+    pub rate: f32,
+    /// Step as often as fits in `frame_budget`, ignoring `rate`.
+    pub max_speed: bool,
+    /// How long a frame may spend stepping at max speed or on queued steps.
+    pub frame_budget: Duration,
+    /// Steps asked for with [`Clock::queue`], taken even while paused.
+    pub queued: u64,
+    // fraction of a generation carried over between frames
+    owed: f32,
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock {
+            paused: false,
+            rate: 1.0,
+            max_speed: false,
+            frame_budget: Duration::from_millis(12),
+            queued: 0,
+            owed: 0.0,
+        }
+    }
+}
+
+impl Clock {
+    pub const MIN_RATE: f32 = 1.0 / 16.0;
+    pub const MAX_RATE: f32 = 1024.0;
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.owed = 0.0;
+    }
+
+    /// Doubles the rate, up to [`Clock::MAX_RATE`].
+    pub fn faster(&mut self) {
+        self.rate = (self.rate * 2.0).min(Clock::MAX_RATE);
+    }
+
+    /// Halves the rate, down to [`Clock::MIN_RATE`].
+    pub fn slower(&mut self) {
+        self.rate = (self.rate / 2.0).max(Clock::MIN_RATE);
+    }
+
+    /// Asks for `n` more steps, run over the next frames as the budget
+    /// allows.
+    pub fn queue(&mut self, n: u64) {
+        self.queued += n;
+    }
+
+    /// Calls `step` for every generation due in a frame that took `delta`.
+    /// Always steps at least once if anything is due, then stops once the
+    /// frame budget is used up. Returns how many steps were taken.
+    pub fn advance(&mut self, delta: Duration, mut step: impl FnMut()) -> u64 {
+        let start = Instant::now();
+        let mut taken = 0;
+        let budget = self.frame_budget;
+        let has_time = |taken: u64| taken == 0 || start.elapsed() < budget;
+
+        while self.queued > 0 && has_time(taken) {
+            step();
+            self.queued -= 1;
+            taken += 1;
+        }
+
+        if self.paused {
+            return taken;
+        }
+
+        if self.max_speed {
+            while has_time(taken) {
+                step();
+                taken += 1;
+            }
+            self.owed = 0.0;
+            return taken;
+        }
+
+        self.owed += delta.as_secs_f32() * self.rate;
+        while self.owed >= 1.0 && has_time(taken) {
+            step();
+            self.owed -= 1.0;
+            taken += 1;
+        }
+        // don't build up a backlog if stepping can't keep up with the rate
+        if self.owed >= 1.0 {
+            self.owed = 0.0;
+        }
+
+        taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: f32) -> Duration {
+        Duration::from_secs_f32(s)
+    }
+
+    #[test]
+    fn steps_at_the_rate() {
+        let mut clock = Clock {
+            rate: 4.0,
+            ..Clock::default()
+        };
+        assert_eq!(clock.advance(secs(0.5), || ()), 2);
+        // a fraction of a generation carries over to the next frame
+        assert_eq!(clock.advance(secs(0.125), || ()), 0);
+        assert_eq!(clock.advance(secs(0.125), || ()), 1);
+
+        clock.slower();
+        clock.slower();
+        assert_eq!(clock.rate, 1.0);
+        let mut steps = 0;
+        for _ in 0..10 {
+            steps += clock.advance(secs(0.1), || ());
+        }
+        assert_eq!(steps, 1);
+    }
+
+    #[test]
+    fn runs_queued_steps_while_paused() {
+        let mut clock = Clock::default();
+        clock.toggle_pause();
+        assert_eq!(clock.advance(secs(10.0), || ()), 0);
+        clock.queue(3);
+        let mut stepped = 0;
+        assert_eq!(clock.advance(secs(0.0), || stepped += 1), 3);
+        assert_eq!((stepped, clock.queued), (3, 0));
+    }
+
+    #[test]
+    fn stops_at_the_frame_budget() {
+        // with no budget at all, a frame still gets one step
+        let mut clock = Clock {
+            max_speed: true,
+            frame_budget: Duration::ZERO,
+            ..Clock::default()
+        };
+        assert_eq!(clock.advance(secs(1.0), || ()), 1);
+
+        clock.queue(5);
+        assert_eq!(clock.advance(secs(0.0), || ()), 1);
+        assert_eq!(clock.queued, 4);
+
+        clock.frame_budget = Duration::from_millis(20);
+        let slow = || std::thread::sleep(Duration::from_millis(5));
+        // each step is at least 5ms, so the fifth never starts
+        let taken = clock.advance(secs(0.0), slow);
+        assert!((1..=4).contains(&taken), "{}", taken);
+
+        // a rate stepping can't keep up with doesn't build a backlog
+        clock.max_speed = false;
+        clock.frame_budget = Duration::ZERO;
+        clock.rate = Clock::MAX_RATE;
+        clock.queued = 0;
+        assert_eq!(clock.advance(secs(1.0), || ()), 1);
+        assert_eq!(clock.advance(secs(0.0), || ()), 0);
+    }
+
+    #[test]
+    fn forgets_owed_time_on_pause() {
+        let mut clock = Clock::default();
+        assert_eq!(clock.advance(secs(0.75), || ()), 0);
+        clock.toggle_pause();
+        assert_eq!(clock.advance(secs(5.0), || ()), 0);
+        clock.toggle_pause();
+        // the 0.75 from before the pause is gone
+        assert_eq!(clock.advance(secs(0.5), || ()), 0);
+        assert_eq!(clock.advance(secs(0.5), || ()), 1);
+    }
+}
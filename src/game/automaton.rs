@@ -0,0 +1,95 @@
+//! What a [`World`](super::World) needs to know to run a cellular automaton.
+
+use std::fmt::Debug;
+
+use super::Pos;
+
+/// Anything a cell can be in. `Default` is the quiescent (dead) state, cells
+/// in it aren't stored.
+pub trait CellState: Copy + PartialEq + Default + Debug + Send + Sync + 'static {}
+
+impl<T: Copy + PartialEq + Default + Debug + Send + Sync + 'static> CellState for T {}
+
+/// How a cell in some state gets drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Appearance {
+    pub color: [f32; 4],
+    /// Relative to the cell mesh, 1.0 fills the cell.
+    pub scale: f32,
+    /// Which of the viewer's cell meshes to draw it with. Ones past the last
+    /// mesh get the last one.
+    pub mesh: usize,
+}
+
+impl Default for Appearance {
+    fn default() -> Appearance {
+        Appearance {
+            color: [0.4, 0.9, 0.5, 1.0],
+            scale: 1.0,
+            mesh: 0,
+        }
+    }
+}
+
+/// A cellular automaton on the integer lattice.
+pub trait Automaton {
+    type State: CellState;
+
+    /// Offsets of the cells a cell looks at. 2D automata keep z = 0.
+    fn neighborhood(&self) -> &[Pos];
+
+    /// The next state of a cell, `neighbors` are in `neighborhood()` order.
+    fn transition(&self, state: Self::State, neighbors: &[Self::State]) -> Self::State;
+
+    /// What a non-quiescent cell looks like in the viewer.
+    fn appearance(&self, _state: Self::State) -> Appearance {
+        Appearance::default()
+    }
+}
+
+pub const MOORE_2D: [Pos; 8] = [
+    //right
+    (1, 0, 0),
+    //left
+    (-1, 0, 0),
+    //top
+    (0, 1, 0),
+    //bottom
+    (0, -1, 0),
+    //top left,
+    (-1, 1, 0),
+    //top right,
+    (1, 1, 0),
+    //bottom left,
+    (-1, -1, 0),
+    //bottom right
+    (1, -1, 0),
+];
+
+pub const VON_NEUMANN_2D: [Pos; 4] = [(1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0)];
+
+pub const MOORE_3D: [Pos; 26] = moore_3d();
+
+pub const VON_NEUMANN_3D: [Pos; 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+const fn moore_3d() -> [Pos; 26] {
+    let mut out = [(0, 0, 0); 26];
+    let mut i = 0;
+    let mut n = 0;
+    while n < 27 {
+        let p = (n % 3 - 1, n / 3 % 3 - 1, n / 9 - 1);
+        if !(p.0 == 0 && p.1 == 0 && p.2 == 0) {
+            out[i] = p;
+            i += 1;
+        }
+        n += 1;
+    }
+    out
+}
@@ -0,0 +1,148 @@
+//! Connected-component labeling, for telling the objects on a board apart.
+
+use std::collections::{HashSet, VecDeque};
+
+pub use super::Pos;
+
+/// Which cells count as touching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// 4 neighbors in 2D, 6 in 3D.
+    Orthogonal,
+    /// 8 neighbors in 2D, 26 in 3D.
+    Moore,
+    /// Anything within a Chebyshev distance of 2 belongs to the same object.
+    Island,
+}
+
+/// Whether to look for neighbors along z as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimensions {
+    Two,
+    Three,
+}
+
+impl Connectivity {
+    fn offsets(self, dims: Dimensions) -> Vec<Pos> {
+        let r = match self {
+            Connectivity::Island => 2i32,
+            _ => 1,
+        };
+        let rz = match dims {
+            Dimensions::Two => 0,
+            Dimensions::Three => r,
+        };
+
+        let mut out = vec![];
+        for x in -r..=r {
+            for y in -r..=r {
+                for z in -rz..=rz {
+                    if (x, y, z) == (0, 0, 0) {
+                        continue;
+                    }
+                    if self == Connectivity::Orthogonal && x.abs() + y.abs() + z.abs() != 1 {
+                        continue;
+                    }
+                    out.push((x, y, z));
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// One connected object. `min` and `max` are its inclusive bounding box.
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub cells: Vec<Pos>,
+    pub min: Pos,
+    pub max: Pos,
+}
+
+impl Component {
+    pub fn population(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+/// Splits `cells` into connected components, ordered by their smallest cell.
+pub fn label(
+    cells: impl IntoIterator<Item = Pos>,
+    connectivity: Connectivity,
+    dims: Dimensions,
+) -> Vec<Component> {
+    let mut unvisited: HashSet<Pos> = cells.into_iter().collect();
+    let offsets = connectivity.offsets(dims);
+    let mut out = vec![];
+
+    // sorted seeds so the output order doesn't depend on hash order
+    let mut seeds: Vec<Pos> = unvisited.iter().copied().collect();
+    seeds.sort_unstable();
+
+    for seed in seeds {
+        if !unvisited.remove(&seed) {
+            continue;
+        }
+
+        let mut cells = vec![];
+        let mut min = seed;
+        let mut max = seed;
+        let mut queue = VecDeque::from([seed]);
+
+        while let Some(c) = queue.pop_front() {
+            min = (min.0.min(c.0), min.1.min(c.1), min.2.min(c.2));
+            max = (max.0.max(c.0), max.1.max(c.1), max.2.max(c.2));
+            cells.push(c);
+
+            for (x, y, z) in offsets.iter() {
+                let n = (c.0 + x, c.1 + y, c.2 + z);
+                if unvisited.remove(&n) {
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        out.push(Component { cells, min, max });
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two diagonal neighbors, one cell two steps off, one cell above
+    const CELLS: [Pos; 4] = [(0, 0, 0), (1, 1, 0), (3, 0, 0), (0, 0, 1)];
+
+    fn sizes(connectivity: Connectivity, dims: Dimensions) -> Vec<usize> {
+        label(CELLS, connectivity, dims)
+            .iter()
+            .map(Component::population)
+            .collect()
+    }
+
+    #[test]
+    fn groups_by_connectivity() {
+        use Connectivity::*;
+        use Dimensions::*;
+        assert_eq!(sizes(Orthogonal, Two), [1, 1, 1, 1]);
+        assert_eq!(sizes(Moore, Two), [2, 1, 1]);
+        assert_eq!(sizes(Island, Two), [3, 1]);
+        assert_eq!(sizes(Orthogonal, Three), [2, 1, 1]);
+        assert_eq!(sizes(Moore, Three), [3, 1]);
+        assert_eq!(sizes(Island, Three), [4]);
+    }
+
+    #[test]
+    fn bounds_each_component() {
+        let components = label(CELLS, Connectivity::Island, Dimensions::Two);
+        assert_eq!(components[0].min, (0, 0, 0));
+        assert_eq!(components[0].max, (3, 1, 0));
+        assert_eq!(
+            (components[1].min, components[1].max),
+            ((0, 0, 1), (0, 0, 1))
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! "Generations" rules: Life-like rules where cells take a few generations
+//! to die, like Brian's Brain (B2/S/C3).
+
+use std::fmt;
+
+use super::automaton::{Appearance, Automaton};
+use super::rule::{Neighborhood, Rule};
+use super::Pos;
+
+/// State 0 is dead and 1 is alive. A live cell that doesn't survive goes
+/// through states 2..`states` before it's dead again, dying cells don't
+/// count as neighbors and can't be reborn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Generations {
+    pub birth: u32,
+    pub survive: u32,
+    pub states: u8,
+    pub neighborhood: Neighborhood,
+}
+
+impl Generations {
+    /// B2/S/C3
+    pub const BRIANS_BRAIN: Generations = Generations {
+        birth: 1 << 2,
+        survive: 0,
+        states: 3,
+        neighborhood: Neighborhood::Moore2D,
+    };
+
+    /// Accepts "B2/S/C3", "B2/S/G3" and the old "/2/3" survive/birth/states
+    /// notation. Like [`Rule::parse`], "/3D" before the state count switches
+    /// to the 3D neighborhood, e.g. "B5/S45/3D/C4".
+    pub fn parse(s: &str) -> Result<Generations, Box<dyn std::error::Error>> {
+        let lower = s.trim().to_ascii_lowercase();
+        let invalid = || format!("invalid generations rule {:?}", s);
+
+        let (life, states) = match lower.rfind(['c', 'g']) {
+            Some(i) => (lower[..i].trim_end_matches('/'), &lower[i + 1..]),
+            None => lower.rsplit_once('/').ok_or_else(invalid)?,
+        };
+        let rule = Rule::parse(life)?;
+        let states: u8 = states.parse().map_err(|_| invalid())?;
+        if states < 2 {
+            return Err(invalid().into());
+        }
+
+        Ok(Generations {
+            birth: rule.birth,
+            survive: rule.survive,
+            states,
+            neighborhood: rule.neighborhood,
+        })
+    }
+}
+
+impl Automaton for Generations {
+    type State = u8;
+
+    fn neighborhood(&self) -> &[Pos] {
+        self.neighborhood.offsets()
+    }
+
+    fn transition(&self, state: u8, neighbors: &[u8]) -> u8 {
+        let n = neighbors.iter().filter(|n| **n == 1).count();
+        match state {
+            0 if self.birth & (1 << n) != 0 => 1,
+            0 => 0,
+            1 if self.survive & (1 << n) != 0 => 1,
+            dying => (dying + 1) % self.states,
+        }
+    }
+
+    // dying cells fade out and shrink, and each dying state gets its own mesh
+    fn appearance(&self, state: u8) -> Appearance {
+        let alive = Appearance::default();
+        if state <= 1 {
+            return alive;
+        }
+        let t = (state - 1) as f32 / (self.states - 1) as f32;
+        let dying = [0.6, 0.1, 0.1, 1.0];
+        let mut color = [0.0; 4];
+        for i in 0..4 {
+            color[i] = alive.color[i] * (1.0 - t) + dying[i] * t;
+        }
+
+        Appearance {
+            color,
+            scale: 1.0 - 0.5 * t,
+            mesh: (state - 1) as usize,
+        }
+    }
+}
+
+impl fmt::Display for Generations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let life = Rule {
+            birth: self.birth,
+            survive: self.survive,
+            neighborhood: self.neighborhood,
+        };
+        write!(f, "{}/C{}", life, self.states)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_notation() {
+        for s in ["B2/S/C3", "b2/s/c3", "B2/S/G3", "/2/3", " B2/S/C3 "] {
+            assert_eq!(
+                Generations::parse(s).unwrap(),
+                Generations::BRIANS_BRAIN,
+                "{}",
+                s
+            );
+        }
+        let star_wars = Generations::parse("B2/S345/C4").unwrap();
+        assert_eq!(star_wars.survive, 1 << 3 | 1 << 4 | 1 << 5);
+        assert_eq!(star_wars.states, 4);
+    }
+
+    #[test]
+    fn keeps_the_3d_neighborhood() {
+        let rule = Generations::parse("B5/S45/3D/C4").unwrap();
+        assert_eq!(rule.neighborhood, Neighborhood::Moore3D);
+        assert_eq!(rule.neighborhood().len(), 26);
+        assert_eq!(Generations::BRIANS_BRAIN.neighborhood().len(), 8);
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        for s in ["B2/S/C3", "B2/S345/C4", "B5/S45/3D/C4", "B3/S4,5,10/3D/C10"] {
+            let rule = Generations::parse(s).unwrap();
+            assert_eq!(rule.to_string(), s);
+            assert_eq!(Generations::parse(&rule.to_string()).unwrap(), rule);
+        }
+    }
+
+    #[test]
+    fn rejects_too_few_states() {
+        for s in [
+            "B2/S/C1",
+            "B2/S/C0",
+            "B2/S/C",
+            "B2/S/Cx",
+            "B2/S/C256",
+            "B2/S",
+            "B2/Sx/C3",
+        ] {
+            assert!(Generations::parse(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn cells_die_through_every_state() {
+        let rule = Generations::parse("B2/S/C4").unwrap();
+        let neighbors = |alive: usize, dying: usize| {
+            let mut n = vec![0u8; 8];
+            n[..alive].fill(1);
+            n[alive..alive + dying].fill(2);
+            n
+        };
+
+        assert_eq!(rule.transition(0, &neighbors(2, 0)), 1);
+        // dying neighbors don't count towards a birth
+        assert_eq!(rule.transition(0, &neighbors(1, 3)), 0);
+        // nothing survives, so a live cell starts dying right away
+        let mut state = 1;
+        let mut seen = vec![state];
+        while state != 0 {
+            // and having live neighbors doesn't bring it back
+            state = rule.transition(state, &neighbors(2, 0));
+            seen.push(state);
+        }
+        assert_eq!(seen, [1, 2, 3, 0]);
+    }
+}
@@ -0,0 +1,282 @@
+//! Stepping back through generations that have already been simulated.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use super::automaton::{Automaton, CellState};
+use super::{Change, Diff, Pos, World};
+
+/// Past generations, kept as one diff per generation plus a full snapshot
+/// (keyframe) every `keyframe_interval` generations, all within `max_bytes`.
+#[derive(Debug)]
+pub struct History<S: CellState = bool> {
+    keyframe_interval: u64,
+    max_bytes: usize,
+    delta_bytes: usize,
+    keyframe_bytes: usize,
+    keyframes: BTreeMap<u64, Vec<(Pos, S)>>,
+    // deltas[i] turns generation first_delta + i - 1 into first_delta + i
+    deltas: VecDeque<Diff<S>>,
+    first_delta: u64,
+}
+
+impl<S: CellState> History<S> {
+    /// An empty history, call [`History::reset`] before recording into it.
+    pub fn new(keyframe_interval: u64, max_bytes: usize) -> History<S> {
+        History {
+            keyframe_interval: keyframe_interval.max(1),
+            max_bytes,
+            delta_bytes: 0,
+            keyframe_bytes: 0,
+            keyframes: BTreeMap::new(),
+            deltas: VecDeque::new(),
+            first_delta: 0,
+        }
+    }
+
+    /// Forgets everything and starts over from the board as it is now.
+    pub fn reset<A: Automaton<State = S>>(&mut self, game: &World<A>) {
+        self.keyframes.clear();
+        self.deltas.clear();
+        self.delta_bytes = 0;
+        self.keyframe_bytes = 0;
+        self.first_delta = game.generation + 1;
+        self.push_keyframe(game);
+    }
+
+    /// Newest generation we can get back to without simulating.
+    pub fn newest(&self) -> u64 {
+        let last_key = self.keyframes.keys().next_back().copied().unwrap_or(0);
+        (self.first_delta + self.deltas.len() as u64)
+            .saturating_sub(1)
+            .max(last_key)
+    }
+
+    /// Oldest generation [`History::seek`] can still reach.
+    pub fn oldest(&self) -> u64 {
+        let first_key = self.keyframes.keys().next().copied().unwrap_or(u64::MAX);
+        first_key.min(self.first_delta.saturating_sub(1))
+    }
+
+    /// Roughly how much memory the stored cells take.
+    pub fn bytes(&self) -> usize {
+        self.delta_bytes + self.keyframe_bytes
+    }
+
+    /// Call after [`Game::update`] with the diff it returned.
+    pub fn record<A: Automaton<State = S>>(&mut self, game: &World<A>, diff: &Diff<S>) {
+        let generation = game.generation;
+        if self.keyframes.is_empty() && self.deltas.is_empty() {
+            self.first_delta = generation;
+        }
+
+        // recording over an old generation means the future we had is stale
+        if generation <= self.newest() {
+            self.truncate(generation - 1);
+        }
+        if generation != self.first_delta + self.deltas.len() as u64 {
+            // there's a gap, the old deltas can't lead here any more
+            self.deltas.clear();
+            self.delta_bytes = 0;
+            self.first_delta = generation;
+        }
+
+        self.delta_bytes += diff_bytes(diff);
+        self.deltas.push_back(diff.clone());
+        if generation.is_multiple_of(self.keyframe_interval) {
+            self.push_keyframe(game);
+        }
+
+        self.evict();
+    }
+
+    /// Moves the game one generation back, false if that one's gone.
+    pub fn back<A: Automaton<State = S>>(&self, game: &mut World<A>) -> bool {
+        match self.delta(game.generation) {
+            Some(diff) => {
+                game.revert(diff);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the game one generation forward if we've already been there.
+    pub fn forward<A: Automaton<State = S>>(&self, game: &mut World<A>) -> bool {
+        match self.delta(game.generation + 1) {
+            Some(diff) => {
+                game.apply(diff);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Puts the game at any generation between `oldest()` and `newest()`.
+    /// Walks the deltas where we still have them and re-simulates from the
+    /// closest keyframe where we don't, whichever takes fewer steps.
+    pub fn seek<A: Automaton<State = S>>(&self, game: &mut World<A>, generation: u64) -> bool {
+        if generation > self.newest() || generation < self.oldest() {
+            return false;
+        }
+
+        let back_steps = (game.generation >= generation && generation + 1 >= self.first_delta)
+            .then(|| game.generation - generation);
+        let forward_steps = (game.generation <= generation).then(|| generation - game.generation);
+        let walk_steps = back_steps.into_iter().chain(forward_steps).min();
+        let keyframe = self.keyframes.range(..=generation).next_back();
+
+        match (walk_steps, keyframe) {
+            (Some(steps), Some((k, cells))) if generation - k < steps => {
+                game.set_states(cells.iter().copied(), *k)
+            }
+            (None, Some((k, cells))) => game.set_states(cells.iter().copied(), *k),
+            (None, None) => return false,
+            _ => (),
+        }
+
+        while game.generation > generation {
+            if !self.back(game) {
+                return false;
+            }
+        }
+        while game.generation < generation {
+            if !self.forward(game) {
+                // evicted, so simulate it again
+                game.update();
+            }
+        }
+
+        true
+    }
+
+    fn delta(&self, generation: u64) -> Option<&Diff<S>> {
+        let i = generation.checked_sub(self.first_delta)?;
+        self.deltas.get(i as usize)
+    }
+
+    fn push_keyframe<A: Automaton<State = S>>(&mut self, game: &World<A>) {
+        let cells: Vec<(Pos, S)> = game.cells().collect();
+        self.keyframe_bytes += keyframe_bytes(&cells);
+        if let Some(old) = self.keyframes.insert(game.generation, cells) {
+            self.keyframe_bytes -= keyframe_bytes(&old);
+        }
+    }
+
+    // drops everything after `generation`
+    fn truncate(&mut self, generation: u64) {
+        let stale = self.keyframes.split_off(&(generation + 1));
+        self.keyframe_bytes -= stale.values().map(|k| keyframe_bytes(k)).sum::<usize>();
+
+        let keep = (generation + 1).saturating_sub(self.first_delta) as usize;
+        while self.deltas.len() > keep {
+            let d = self.deltas.pop_back().unwrap();
+            self.delta_bytes -= diff_bytes(&d);
+        }
+    }
+
+    // Deltas go first, oldest first, but only while they use at least half
+    // the budget; past that the oldest keyframes go instead, so there's
+    // always something sparse to fall back on.
+    fn evict(&mut self) {
+        while self.bytes() > self.max_bytes {
+            if (self.delta_bytes >= self.max_bytes / 2 || self.keyframes.len() <= 1)
+                && self.deltas.len() > 1
+            {
+                let d = self.deltas.pop_front().unwrap();
+                self.delta_bytes -= diff_bytes(&d);
+                self.first_delta += 1;
+            } else if self.keyframes.len() > 1 {
+                let oldest = *self.keyframes.keys().next().unwrap();
+                let k = self.keyframes.remove(&oldest).unwrap();
+                self.keyframe_bytes -= keyframe_bytes(&k);
+            } else {
+                // a single generation's worth is bigger than the whole budget
+                break;
+            }
+        }
+    }
+}
+
+fn diff_bytes<S>(diff: &Diff<S>) -> usize {
+    diff.changes.len() * std::mem::size_of::<Change<S>>()
+}
+
+fn keyframe_bytes<S>(cells: &[(Pos, S)]) -> usize {
+    std::mem::size_of_val(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+
+    fn cells(game: &Game) -> Vec<Pos> {
+        let mut cells = game.live_cells();
+        cells.sort_unstable();
+        cells
+    }
+
+    // an R-pentomino run for `generations` with everything it went through
+    fn run(history: &mut History, generations: usize) -> (Game, Vec<Vec<Pos>>) {
+        let mut game = Game::new();
+        history.reset(&game);
+        let mut seen = vec![cells(&game)];
+        for _ in 0..generations {
+            let diff = game.update();
+            history.record(&game, &diff);
+            seen.push(cells(&game));
+        }
+        (game, seen)
+    }
+
+    #[test]
+    fn steps_back_and_forward() {
+        let mut history = History::new(8, usize::MAX);
+        let (mut game, seen) = run(&mut history, 30);
+        for g in (0..30).rev() {
+            assert!(history.back(&mut game));
+            assert_eq!(cells(&game), seen[g]);
+        }
+        assert!(!history.back(&mut game));
+        for later in seen.iter().skip(1) {
+            assert!(history.forward(&mut game));
+            assert_eq!(&cells(&game), later);
+        }
+        assert!(!history.forward(&mut game));
+    }
+
+    #[test]
+    fn seeks_within_a_tight_budget() {
+        let max_bytes = 16 * 1024;
+        let mut history = History::new(16, max_bytes);
+        let (mut game, seen) = run(&mut history, 300);
+        assert!(history.bytes() <= max_bytes);
+        assert!(history.oldest() > 0, "nothing was evicted");
+        assert_eq!(history.newest(), 300);
+
+        // both ends, either side of a keyframe, and a spread in between
+        let (oldest, newest) = (history.oldest(), history.newest());
+        let mut targets = vec![oldest, oldest + 1, newest - 1, newest];
+        targets.extend((oldest..=newest).step_by(7));
+        targets.extend([oldest / 16 * 16 + 15, oldest / 16 * 16 + 16]);
+        for g in targets {
+            assert!(history.seek(&mut game, g), "{}", g);
+            assert_eq!(game.generation(), g);
+            assert_eq!(cells(&game), seen[g as usize], "{}", g);
+        }
+        assert!(!history.seek(&mut game, newest + 1));
+        assert!(!history.seek(&mut game, oldest - 1));
+    }
+
+    #[test]
+    fn recording_over_the_past_drops_the_old_future() {
+        let mut history = History::new(4, usize::MAX);
+        let (mut game, _) = run(&mut history, 20);
+        assert!(history.seek(&mut game, 10));
+        game.set((100, 100, 0), true);
+        let diff = game.update();
+        history.record(&game, &diff);
+        assert_eq!(history.newest(), 11);
+        assert!(!history.forward(&mut game));
+    }
+}
@@ -0,0 +1,243 @@
+//! Classic patterns compiled into the crate, loadable by name.
+
+use super::pattern::{self, Pattern};
+use super::rule::Rule;
+use super::Pos;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Spaceship,
+    Oscillator,
+    Gun,
+    /// Small patterns that take a long time to settle (or die).
+    Methuselah,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Cells {
+    Rle(&'static str),
+    /// For 3D patterns, which read better as a list than as layers of RLE.
+    List(&'static [Pos]),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub rule: Rule,
+    pub kind: Kind,
+    pub period: Option<u32>,
+    pub discovered: Option<&'static str>,
+    pub description: &'static str,
+    pub cells: Cells,
+}
+
+impl Entry {
+    pub fn pattern(&self) -> Pattern {
+        let cells = match self.cells {
+            // the RLE here is known good
+            Cells::Rle(rle) => pattern::parse_rle(rle).unwrap().cells,
+            Cells::List(cells) => cells.to_vec(),
+        };
+
+        Pattern {
+            cells,
+            rule: Some(self.rule.into()),
+            name: Some(self.name.to_string()),
+        }
+    }
+}
+
+pub const PATTERNS: &[Entry] = &[
+    Entry {
+        name: "glider",
+        aliases: &[],
+        rule: Rule::CONWAY,
+        kind: Kind::Spaceship,
+        period: Some(4),
+        discovered: Some("Richard K. Guy, 1969"),
+        description: "The smallest spaceship, moves diagonally at c/4.",
+        cells: Cells::Rle("bo$2bo$3o!"),
+    },
+    Entry {
+        name: "lwss",
+        aliases: &["lightweight-spaceship"],
+        rule: Rule::CONWAY,
+        kind: Kind::Spaceship,
+        period: Some(4),
+        discovered: Some("John Conway, 1970"),
+        description: "Lightweight spaceship, moves orthogonally at c/2.",
+        cells: Cells::Rle("bo2bo$o4b$o3bo$4o!"),
+    },
+    Entry {
+        name: "mwss",
+        aliases: &["middleweight-spaceship"],
+        rule: Rule::CONWAY,
+        kind: Kind::Spaceship,
+        period: Some(4),
+        discovered: Some("John Conway, 1970"),
+        description: "Middleweight spaceship, moves orthogonally at c/2.",
+        cells: Cells::Rle("3bo2b$bo3bo$o5b$o4bo$5o!"),
+    },
+    Entry {
+        name: "hwss",
+        aliases: &["heavyweight-spaceship"],
+        rule: Rule::CONWAY,
+        kind: Kind::Spaceship,
+        period: Some(4),
+        discovered: Some("John Conway, 1970"),
+        description: "Heavyweight spaceship, moves orthogonally at c/2.",
+        cells: Cells::Rle("3b2o2b$bo4bo$o6b$o5bo$6o!"),
+    },
+    Entry {
+        name: "gosper-glider-gun",
+        aliases: &["gosper", "gosper-gun"],
+        rule: Rule::CONWAY,
+        kind: Kind::Gun,
+        period: Some(30),
+        discovered: Some("Bill Gosper, 1970"),
+        description: "The first known gun, fires a glider every 30 generations.",
+        cells: Cells::Rle(
+            "24bo11b$22bobo11b$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o14b$\
+             2o8bo3bob2o4bobo11b$10bo5bo7bo11b$11bo3bo20b$12b2o!",
+        ),
+    },
+    Entry {
+        name: "simkin-glider-gun",
+        aliases: &["simkin", "simkin-gun"],
+        rule: Rule::CONWAY,
+        kind: Kind::Gun,
+        period: Some(120),
+        discovered: Some("Michael Simkin, 2015"),
+        description: "The smallest known gun by population, fires a glider every 120 generations.",
+        cells: Cells::Rle(
+            "2o5b2o$2o5b2o2$4b2o$4b2o5$22b2ob2o$21bo5bo$21bo6bo2b2o$21b3o3bo3b2o$\
+             26bo4$20b2o$20bo$21b3o$23bo!",
+        ),
+    },
+    Entry {
+        name: "pulsar",
+        aliases: &[],
+        rule: Rule::CONWAY,
+        kind: Kind::Oscillator,
+        period: Some(3),
+        discovered: Some("John Conway, 1970"),
+        description: "The most common period 3 oscillator.",
+        cells: Cells::Rle(
+            "2b3o3b3o2b2$o4bobo4bo$o4bobo4bo$o4bobo4bo$2b3o3b3o2b2$2b3o3b3o2b$\
+             o4bobo4bo$o4bobo4bo$o4bobo4bo2$2b3o3b3o!",
+        ),
+    },
+    Entry {
+        name: "pentadecathlon",
+        aliases: &[],
+        rule: Rule::CONWAY,
+        kind: Kind::Oscillator,
+        period: Some(15),
+        discovered: Some("John Conway, 1970"),
+        description: "A period 15 oscillator that grows out of a row of ten cells.",
+        cells: Cells::Rle("2bo4bo2b$2ob4ob2o$2bo4bo!"),
+    },
+    Entry {
+        name: "r-pentomino",
+        aliases: &["r"],
+        rule: Rule::CONWAY,
+        kind: Kind::Methuselah,
+        period: None,
+        discovered: Some("John Conway, 1969"),
+        description: "Five cells that take 1103 generations to settle.",
+        cells: Cells::Rle("b2o$2o$bo!"),
+    },
+    Entry {
+        name: "acorn",
+        aliases: &[],
+        rule: Rule::CONWAY,
+        kind: Kind::Methuselah,
+        period: None,
+        discovered: Some("Charles Corderman, 1971"),
+        description: "Seven cells that take 5206 generations to settle.",
+        cells: Cells::Rle("bo5b$3bo3b$2o2b3o!"),
+    },
+    Entry {
+        name: "diehard",
+        aliases: &[],
+        rule: Rule::CONWAY,
+        kind: Kind::Methuselah,
+        period: None,
+        discovered: None,
+        description: "Seven cells that die out completely after 130 generations.",
+        cells: Cells::Rle("6bob$2o6b$bo3b3o!"),
+    },
+    Entry {
+        name: "glider-4555",
+        aliases: &["bays-glider", "3d-glider"],
+        rule: Rule::LIFE_4555,
+        kind: Kind::Spaceship,
+        period: Some(4),
+        discovered: Some("Carter Bays, 1987"),
+        description: "The 3D Life 4555 glider, moves diagonally in the y/z plane at c/4.",
+        cells: Cells::List(&[
+            (0, 1, 1),
+            (0, 2, 1),
+            (1, 0, 1),
+            (1, 1, 0),
+            (1, 2, 0),
+            (2, 0, 1),
+            (2, 1, 0),
+            (2, 2, 0),
+            (3, 1, 1),
+            (3, 2, 1),
+        ]),
+    },
+    Entry {
+        name: "glider-5766",
+        aliases: &[],
+        rule: Rule::LIFE_5766,
+        kind: Kind::Spaceship,
+        period: Some(4),
+        discovered: Some("Carter Bays, 1987"),
+        description: "The 3D Life 5766 glider, moves diagonally in the y/z plane at c/4.",
+        cells: Cells::List(&[
+            (0, 0, 0),
+            (0, 0, 1),
+            (0, 0, 2),
+            (0, 1, 2),
+            (0, 2, 1),
+            (1, 0, 0),
+            (1, 0, 1),
+            (1, 0, 2),
+            (1, 1, 2),
+            (1, 2, 1),
+        ]),
+    },
+];
+
+// "Gosper Glider_Gun" and "gosper-glider-gun" are the same thing
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '-' | '_' | ' '))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Looks a pattern up by name or alias, ignoring case and separators.
+pub fn get(name: &str) -> Option<&'static Entry> {
+    let name = normalize(name);
+    PATTERNS
+        .iter()
+        .find(|e| normalize(e.name) == name || e.aliases.iter().any(|a| normalize(a) == name))
+}
+
+/// A library pattern if there's one called `name`, otherwise the pattern
+/// file at that path.
+pub fn load(name: &str) -> Result<Pattern, Box<dyn std::error::Error>> {
+    if !std::path::Path::new(name).exists() {
+        return match get(name) {
+            Some(entry) => Ok(entry.pattern()),
+            None => {
+                Err(format!("{} is neither a pattern file nor a built-in pattern", name).into())
+            }
+        };
+    }
+    pattern::load(name)
+}
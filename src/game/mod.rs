@@ -1,117 +1,508 @@
-use std::{cell::Cell, collections::HashMap, hash::Hash};
-use rand::Rng;
-
-type CellList = HashMap<(i32, i32), bool>;
-
-const offsets: [(i32, i32); 8] = [
-    //right
-    (1, 0),
-    //left
-    (-1, 0),
-    //top
-    (0, 1),
-    //bottom
-    (0, -1),
-    //top left,
-    (-1, 1),
-    //top right,
-    (1, 1),
-    //bottom left,
-    (-1, -1),
-    //bottom right
-    (1, -1),
-];
-
-#[derive(Debug)]
-pub struct Game {
-    pub list: CellList,
+//! The board and everything that reads or changes it.
+
+use std::collections::{HashMap, HashSet};
+
+pub mod automaton;
+pub mod components;
+pub mod generations;
+pub mod history;
+pub mod library;
+pub mod pattern;
+pub mod rule;
+
+use automaton::{Automaton, CellState};
+use components::{Component, Connectivity, Dimensions};
+use rule::Rule;
+
+/// A cell position. 2D automata keep everything at z = 0.
+pub type Pos = (i32, i32, i32);
+
+pub type CellList<S = bool> = HashMap<Pos, S>;
+
+/// Inclusive (min, max) corners of `cells`, None if there aren't any.
+pub fn bounds(cells: impl IntoIterator<Item = Pos>) -> Option<(Pos, Pos)> {
+    let mut cells = cells.into_iter();
+    let first = cells.next()?;
+    Some(cells.fold((first, first), |(min, max), c| {
+        (
+            (min.0.min(c.0), min.1.min(c.1), min.2.min(c.2)),
+            (max.0.max(c.0), max.1.max(c.1), max.2.max(c.2)),
+        )
+    }))
+}
+
+/// One cell changing state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Change<S = bool> {
+    pub pos: Pos,
+    pub before: S,
+    pub after: S,
+}
+
+/// What changed between two consecutive generations.
+#[derive(Debug, Clone)]
+pub struct Diff<S = bool> {
+    pub changes: Vec<Change<S>>,
+}
+
+impl<S> Default for Diff<S> {
+    fn default() -> Diff<S> {
+        Diff { changes: vec![] }
+    }
+}
+
+impl<S: CellState> Diff<S> {
+    /// Cells that left the quiescent state.
+    pub fn born(&self) -> impl Iterator<Item = Pos> + '_ {
+        self.changes
+            .iter()
+            .filter(|c| c.before == S::default())
+            .map(|c| c.pos)
+    }
+
+    /// Cells that went quiescent.
+    pub fn died(&self) -> impl Iterator<Item = Pos> + '_ {
+        self.changes
+            .iter()
+            .filter(|c| c.after == S::default())
+            .map(|c| c.pos)
+    }
+
+    /// The diff that undoes this one.
+    pub fn inverted(&self) -> Diff<S> {
+        Diff {
+            changes: self
+                .changes
+                .iter()
+                .map(|c| Change {
+                    pos: c.pos,
+                    before: c.after,
+                    after: c.before,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Called with the board and what just changed on it, see
+/// [`World::subscribe`].
+pub type Observer<A> = Box<dyn FnMut(&World<A>, &Diff<<A as Automaton>::State>) + Send + Sync>;
+
+/// An unbounded board run by some [`Automaton`].
+///
+/// Only cells that aren't in the quiescent state are stored. Every change
+/// goes through the methods so observers hear about it.
+pub struct World<A: Automaton> {
+    list: CellList<A::State>,
+    generation: u64,
+    pub rule: A,
+    observers: Vec<(usize, Observer<A>)>,
+    next_observer: usize,
+}
+
+impl<A: Automaton + std::fmt::Debug> std::fmt::Debug for World<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("World")
+            .field("list", &self.list)
+            .field("generation", &self.generation)
+            .field("rule", &self.rule)
+            .field("observers", &self.observers.len())
+            .finish()
+    }
+}
+
+/// The Life-like board the viewer runs.
+pub type Game = World<Rule>;
+
+impl Default for Game {
+    fn default() -> Game {
+        Game::new()
+    }
 }
 
 impl Game {
+    /// The viewer's starting board, an R-pentomino.
     pub fn new() -> Game {
-        let mut list = CellList::new();
-        let size = 0..10;
-        list.insert((0, 0), true);
-        list.insert((1, 0), true);
-        list.insert((1, 1), true);
-        list.insert((2, 1), true);
-        for i in size.clone() {
-            for j in size.clone() {
-                match list.get(&(i, j)) {
-                    Some(_) => (),
-                    None => { list.insert((i, j), false); },
-                };
-            }
+        let start = library::get("r-pentomino").unwrap().pattern();
+        Game::with_cells(start.cells, Rule::CONWAY)
+    }
+}
+
+impl<A: Automaton> World<A> {
+    /// A board at generation 0 with `cells` alive.
+    pub fn with_cells(cells: impl IntoIterator<Item = Pos>, rule: A) -> World<A>
+    where
+        A::State: From<bool>,
+    {
+        let mut game = World::empty(rule);
+        game.set_cells(cells, 0);
+        game
+    }
+
+    /// A board with nothing on it.
+    pub fn empty(rule: A) -> World<A> {
+        World {
+            list: CellList::new(),
+            generation: 0,
+            rule,
+            observers: vec![],
+            next_observer: 0,
         }
-        Game {
-            list,
+    }
+
+    /// Calls `observer` after every change to the board, whether it's a new
+    /// generation, a step through history or an edit. Returns an id for
+    /// [`World::unsubscribe`].
+    pub fn subscribe(
+        &mut self,
+        observer: impl FnMut(&World<A>, &Diff<A::State>) + Send + Sync + 'static,
+    ) -> usize {
+        let id = self.next_observer;
+        self.next_observer += 1;
+        self.observers.push((id, Box::new(observer)));
+        id
+    }
+
+    /// Stops calling an observer, false if there wasn't one with that id.
+    pub fn unsubscribe(&mut self, id: usize) -> bool {
+        let before = self.observers.len();
+        self.observers.retain(|(i, _)| *i != id);
+        self.observers.len() != before
+    }
+
+    fn notify(&mut self, diff: &Diff<A::State>) {
+        if self.observers.is_empty() || diff.changes.is_empty() {
+            return;
         }
+        let mut observers = std::mem::take(&mut self.observers);
+        for (_, observer) in observers.iter_mut() {
+            observer(self, diff);
+        }
+        self.observers = observers;
     }
 
-    pub fn make_list(&self) -> Vec<f32> {
-        let instance = crate::model::Instance {
-            position: na::Point3::new(0.0, 0.0, 0.0),
-            rotation: na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), 0.0),
-        };
-        let instance2 = crate::model::Instance {
-            position: na::Point3::new(1.0, 1.0, 0.0),
-            rotation: na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), 0.0),
+    // sets a cell without telling anyone, returns what it was before
+    fn put(&mut self, pos: Pos, state: A::State) -> A::State {
+        let before = if state == A::State::default() {
+            self.list.remove(&pos)
+        } else {
+            self.list.insert(pos, state)
         };
+        before.unwrap_or_default()
+    }
 
-        let mut instances = vec![];
+    /// How many generations the board has been stepped, or the one it was
+    /// set to.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
 
-        for (k, v) in self.list.iter() {
-            if *v {
-                instances.push(crate::model::Instance {
-                    position: na::Point3::new(k.0 as f32, k.1 as f32, 0.0),
-                    rotation: na::UnitQuaternion::from_euler_angles(0.0, 0.0, 0.0),
-                })
+    /// The state of a cell, quiescent ones included.
+    pub fn get(&self, pos: Pos) -> A::State {
+        self.list.get(&pos).copied().unwrap_or_default()
+    }
+
+    /// Sets one cell without counting it as a generation.
+    pub fn set(&mut self, pos: Pos, state: A::State) {
+        self.edit([(pos, state)]);
+    }
+
+    /// Sets a batch of cells without counting it as a generation, observers
+    /// hear about it once. Returns the cells that changed.
+    pub fn edit(&mut self, cells: impl IntoIterator<Item = (Pos, A::State)>) -> Diff<A::State> {
+        let mut diff = Diff::default();
+        for (pos, after) in cells {
+            let before = self.put(pos, after);
+            if before != after {
+                diff.changes.push(Change { pos, before, after });
             }
         }
+        self.notify(&diff);
+        diff
+    }
+
+    /// How many cells aren't quiescent.
+    pub fn population(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Inclusive (min, max) corners of the live cells, None for an empty
+    /// board.
+    pub fn bounds(&self) -> Option<(Pos, Pos)> {
+        bounds(self.list.keys().copied())
+    }
+
+    /// Every live cell and its state, in no particular order.
+    pub fn cells(&self) -> impl Iterator<Item = (Pos, A::State)> + '_ {
+        self.list.iter().map(|(c, s)| (*c, *s))
+    }
+
+    /// Live cells inside the inclusive box from `min` to `max`, in no
+    /// particular order.
+    pub fn cells_in(&self, min: Pos, max: Pos) -> impl Iterator<Item = (Pos, A::State)> + '_ {
+        let inside = move |c: &Pos| {
+            (min.0..=max.0).contains(&c.0)
+                && (min.1..=max.1).contains(&c.1)
+                && (min.2..=max.2).contains(&c.2)
+        };
+        // wide boxes don't fit in i32, let alone their volume
+        let volume = [(min.0, max.0), (min.1, max.1), (min.2, max.2)]
+            .iter()
+            .map(|&(lo, hi)| (hi as i64 - lo as i64 + 1).max(0) as u64)
+            .fold(1u64, u64::saturating_mul);
+
+        // look up every cell of a small box, scan the board for a big one
+        let (probe, scan) = if volume < self.list.len() as u64 {
+            (Some(min.0..=max.0), None)
+        } else {
+            (None, Some(self.list.iter().filter(move |(c, _)| inside(c))))
+        };
+        let probed = probe.into_iter().flatten().flat_map(move |x| {
+            (min.1..=max.1).flat_map(move |y| {
+                (min.2..=max.2).filter_map(move |z| self.list.get_key_value(&(x, y, z)))
+            })
+        });
+
+        probed
+            .chain(scan.into_iter().flatten())
+            .map(|(c, s)| (*c, *s))
+    }
+
+    /// True if cells look at neighbors off their own z plane.
+    pub fn is_3d(&self) -> bool {
+        self.rule.neighborhood().iter().any(|o| o.2 != 0)
+    }
+
+    /// Groups the live cells into separate objects.
+    pub fn components(&self, connectivity: Connectivity) -> Vec<Component> {
+        let dims = if self.is_3d() {
+            Dimensions::Three
+        } else {
+            Dimensions::Two
+        };
+
+        components::label(self.list.keys().copied(), connectivity, dims)
+    }
+
+    /// Every live cell, in no particular order.
+    pub fn live_cells(&self) -> Vec<Pos> {
+        self.list.keys().copied().collect()
+    }
+
+    /// Replaces the board without touching the rule, every cell in `cells`
+    /// gets the first state after the quiescent one.
+    pub fn set_cells(&mut self, cells: impl IntoIterator<Item = Pos>, generation: u64)
+    where
+        A::State: From<bool>,
+    {
+        self.set_states(cells.into_iter().map(|c| (c, true.into())), generation);
+    }
+
+    /// Replaces the board without touching the rule.
+    pub fn set_states(
+        &mut self,
+        cells: impl IntoIterator<Item = (Pos, A::State)>,
+        generation: u64,
+    ) {
+        let old = std::mem::take(&mut self.list);
+        for (pos, state) in cells {
+            self.put(pos, state);
+        }
+        self.generation = generation;
 
-        let mut out = vec![];
+        if self.observers.is_empty() {
+            return;
+        }
+        let mut diff = Diff::default();
+        for (pos, before) in old.iter() {
+            let after = self.get(*pos);
+            if after != *before {
+                diff.changes.push(Change {
+                    pos: *pos,
+                    before: *before,
+                    after,
+                });
+            }
+        }
+        for (pos, after) in self.list.iter() {
+            if !old.contains_key(pos) {
+                diff.changes.push(Change {
+                    pos: *pos,
+                    before: A::State::default(),
+                    after: *after,
+                });
+            }
+        }
+        self.notify(&diff);
+    }
 
-        for i in instances {
-            out.append(&mut i.to_raw());
+    /// Moves forward one generation along an already known diff.
+    pub fn apply(&mut self, diff: &Diff<A::State>) {
+        for c in diff.changes.iter() {
+            self.put(c.pos, c.after);
         }
+        self.generation += 1;
+        self.notify(diff);
+    }
 
-        out
+    /// Undoes the diff that produced the current generation.
+    pub fn revert(&mut self, diff: &Diff<A::State>) {
+        for c in diff.changes.iter() {
+            self.put(c.pos, c.before);
+        }
+        self.generation -= 1;
+        if !self.observers.is_empty() {
+            self.notify(&diff.inverted());
+        }
     }
 
-    pub fn update(&mut self) {
-        let mut changes = vec![];
-        for (k, v) in self.list.iter() {
-            let neighbors = self.get_neighbors(*k);
-            if neighbors < 2 || neighbors > 3 {
-                changes.push((k.0, k.1, false));
+    /// Steps the board one generation with its rule.
+    pub fn update(&mut self) -> Diff<A::State> {
+        let offsets = self.rule.neighborhood();
+
+        // only live cells and the cells that can see them can change
+        let mut candidates = HashSet::new();
+        for k in self.list.keys() {
+            candidates.insert(*k);
+            for (x, y, z) in offsets {
+                candidates.insert((k.0 - x, k.1 - y, k.2 - z));
             }
+        }
 
-            if neighbors == 3 && !*v {
-                changes.push((k.0, k.1, true));
+        let mut diff = Diff::default();
+        let mut neighbors = Vec::with_capacity(offsets.len());
+        for k in candidates {
+            neighbors.clear();
+            for (x, y, z) in offsets {
+                neighbors.push(self.get((k.0 + x, k.1 + y, k.2 + z)));
             }
 
-            if neighbors == 2 || neighbors == 3 {
-                //nothing
+            let before = self.get(k);
+            let after = self.rule.transition(before, &neighbors);
+            if after != before {
+                diff.changes.push(Change {
+                    pos: k,
+                    before,
+                    after,
+                });
             }
         }
 
-        for c in changes {
-            self.list.insert((c.0, c.1), c.2);
+        for c in diff.changes.iter() {
+            self.put(c.pos, c.after);
         }
+        self.generation += 1;
+        self.notify(&diff);
+
+        diff
     }
+}
 
-    fn get_neighbors(&self, cell: (i32, i32)) -> u32 {
-        let mut count: u32 = 0;
-        for (x, y) in offsets {
-            match self.list.get(&(cell.0 + x, cell.1 + y)) {
-                Some(alive) => if *alive {
-                    count += 1;
-                },
-                None => (),
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        count
+    // a few hundred cells scattered through a 16x16x4 box around the origin
+    fn scattered() -> Game {
+        let mut seed = 0x2545_f491u32;
+        let cells = (0..400).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let c = |shift: u32, size: u32| ((seed >> shift) % size) as i32 - size as i32 / 2;
+            (c(0, 16), c(8, 16), c(16, 4))
+        });
+        Game::with_cells(cells, Rule::LIFE_4555)
     }
-}
 
+    fn sorted(cells: impl Iterator<Item = (Pos, bool)>) -> Vec<Pos> {
+        let mut cells: Vec<Pos> = cells.map(|(c, _)| c).collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    fn check_cells_in(game: &Game, min: Pos, max: Pos) {
+        let inside = |c: &Pos| {
+            min.0 <= c.0
+                && c.0 <= max.0
+                && min.1 <= c.1
+                && c.1 <= max.1
+                && min.2 <= c.2
+                && c.2 <= max.2
+        };
+        let expected = sorted(game.cells().filter(|(c, _)| inside(c)));
+        assert_eq!(
+            sorted(game.cells_in(min, max)),
+            expected,
+            "{:?}..{:?}",
+            min,
+            max
+        );
+    }
+
+    #[test]
+    fn probes_small_boxes() {
+        let game = scattered();
+        // far fewer cells in the box than on the board
+        check_cells_in(&game, (-2, -2, 0), (2, 2, 0));
+        check_cells_in(&game, (0, 0, 0), (0, 0, 0));
+        check_cells_in(&game, (7, 7, 1), (9, 9, 1));
+    }
+
+    #[test]
+    fn scans_big_boxes() {
+        let game = scattered();
+        check_cells_in(&game, (-8, -8, -2), (7, 7, 1));
+        check_cells_in(&game, (-100, -3, -100), (100, 3, 100));
+        // far past what the volume fits in, even as u64
+        let (lo, hi) = (i32::MIN, i32::MAX);
+        check_cells_in(&game, (lo, lo, lo), (hi, hi, hi));
+        check_cells_in(&game, (lo, 0, lo), (hi, 0, hi));
+        assert_eq!(
+            game.cells_in((lo, lo, lo), (hi, hi, hi)).count(),
+            game.population()
+        );
+    }
+
+    #[test]
+    fn finds_nothing_in_inverted_boxes() {
+        let game = scattered();
+        check_cells_in(&game, (2, -2, 0), (-2, 2, 0));
+        check_cells_in(&game, (i32::MAX, 0, 0), (i32::MIN, 0, 0));
+        assert_eq!(game.cells_in((1, 1, 1), (0, 0, 0)).count(), 0);
+    }
+
+    #[test]
+    fn observers_hear_every_change_until_unsubscribed() {
+        use std::sync::{Arc, Mutex};
+
+        let mut game = Game::with_cells([(0, 0, 0), (1, 0, 0), (2, 0, 0)], Rule::CONWAY);
+        let heard = Arc::new(Mutex::new(vec![]));
+        let log = heard.clone();
+        let id = game.subscribe(move |world, diff| {
+            let mut born: Vec<Pos> = diff.born().collect();
+            let mut died: Vec<Pos> = diff.died().collect();
+            born.sort_unstable();
+            died.sort_unstable();
+            log.lock().unwrap().push((world.generation(), born, died));
+        });
+
+        game.update();
+        game.edit([((5, 5, 0), true), ((1, 0, 0), true)]);
+        // nothing changed, so nothing to hear
+        game.edit([((5, 5, 0), true)]);
+        assert_eq!(
+            *heard.lock().unwrap(),
+            [
+                (1, vec![(1, -1, 0), (1, 1, 0)], vec![(0, 0, 0), (2, 0, 0)]),
+                (1, vec![(5, 5, 0)], vec![]),
+            ]
+        );
+
+        assert!(game.unsubscribe(id));
+        assert!(!game.unsubscribe(id));
+        game.update();
+        game.set((9, 9, 0), true);
+        assert_eq!(heard.lock().unwrap().len(), 2);
+    }
+}
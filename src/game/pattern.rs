@@ -0,0 +1,625 @@
+//! Reading, writing, transforming and stamping patterns.
+
+use std::collections::{BTreeSet, HashSet};
+
+use super::automaton::Automaton;
+use super::rule::{AnyRule, Rule};
+use super::{Diff, Pos, World};
+
+/// A set of live cells, optionally with the rule it's meant for.
+///
+/// y grows upwards like the rest of the board, so the first row of a file
+/// ends up at y = 0 and the rows below it at negative y.
+#[derive(Debug, Clone, Default)]
+pub struct Pattern {
+    pub cells: Vec<Pos>,
+    pub rule: Option<AnyRule>,
+    pub name: Option<String>,
+}
+
+/// One of the 48 rotations and reflections of the cube, the 8 that keep
+/// z alone are the symmetries of the square.
+///
+/// Output axis `i` is input axis `axes[i]`, negated if `flip[i]` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symmetry {
+    pub axes: [usize; 3],
+    pub flip: [bool; 3],
+}
+
+impl Symmetry {
+    pub const IDENTITY: Symmetry = Symmetry {
+        axes: [0, 1, 2],
+        flip: [false; 3],
+    };
+
+    /// A quarter turn counterclockwise around the z axis, x goes to y.
+    pub const ROTATE_Z: Symmetry = Symmetry {
+        axes: [1, 0, 2],
+        flip: [true, false, false],
+    };
+
+    /// A quarter turn around the x axis, y goes to z.
+    pub const ROTATE_X: Symmetry = Symmetry {
+        axes: [0, 2, 1],
+        flip: [false, true, false],
+    };
+
+    /// A quarter turn around the y axis, z goes to x.
+    pub const ROTATE_Y: Symmetry = Symmetry {
+        axes: [2, 1, 0],
+        flip: [false, false, true],
+    };
+
+    /// Mirrors left and right.
+    pub const FLIP_X: Symmetry = Symmetry {
+        axes: [0, 1, 2],
+        flip: [true, false, false],
+    };
+
+    /// Mirrors top and bottom.
+    pub const FLIP_Y: Symmetry = Symmetry {
+        axes: [0, 1, 2],
+        flip: [false, true, false],
+    };
+
+    /// Mirrors front and back.
+    pub const FLIP_Z: Symmetry = Symmetry {
+        axes: [0, 1, 2],
+        flip: [false, false, true],
+    };
+
+    pub fn apply(&self, pos: Pos) -> Pos {
+        let p = [pos.0, pos.1, pos.2];
+        let axis = |i: usize| {
+            if self.flip[i] {
+                -p[self.axes[i]]
+            } else {
+                p[self.axes[i]]
+            }
+        };
+        (axis(0), axis(1), axis(2))
+    }
+
+    /// `self` followed by `next`.
+    pub fn then(&self, next: Symmetry) -> Symmetry {
+        let mut out = Symmetry::IDENTITY;
+        for i in 0..3 {
+            out.axes[i] = self.axes[next.axes[i]];
+            out.flip[i] = next.flip[i] ^ self.flip[next.axes[i]];
+        }
+        out
+    }
+
+    /// The symmetry that undoes this one.
+    pub fn inverse(&self) -> Symmetry {
+        let mut out = Symmetry::IDENTITY;
+        for i in 0..3 {
+            out.axes[self.axes[i]] = i;
+            out.flip[self.axes[i]] = self.flip[i];
+        }
+        out
+    }
+
+    /// True for rotations, false for anything that mirrors.
+    pub fn is_rotation(&self) -> bool {
+        // parity of the permutation plus the number of flips
+        let swaps = (0..3)
+            .flat_map(|i| (i + 1..3).map(move |j| (i, j)))
+            .filter(|&(i, j)| self.axes[i] > self.axes[j])
+            .count();
+        let flips = self.flip.iter().filter(|f| **f).count();
+        (swaps + flips).is_multiple_of(2)
+    }
+
+    /// All 48 symmetries of the cube, the identity first.
+    pub fn all() -> impl Iterator<Item = Symmetry> {
+        const PERMUTATIONS: [[usize; 3]; 6] = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        PERMUTATIONS.into_iter().flat_map(|axes| {
+            (0..8).map(move |bits| Symmetry {
+                axes,
+                flip: [bits & 1 != 0, bits & 2 != 0, bits & 4 != 0],
+            })
+        })
+    }
+
+    /// The 8 symmetries of the square in the x/y plane, the identity first.
+    pub fn planar() -> impl Iterator<Item = Symmetry> {
+        Symmetry::all().filter(|s| s.axes[2] == 2 && !s.flip[2])
+    }
+}
+
+/// How [`Pattern::stamp`] combines a pattern with what's already on the
+/// board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PasteMode {
+    /// Pattern cells come alive, everything else is left alone.
+    #[default]
+    Or,
+    /// Pattern cells flip.
+    Xor,
+    /// Pattern cells die.
+    AndNot,
+    /// The pattern's bounding box is cleared first, so the board ends up
+    /// matching the pattern exactly inside it.
+    Copy,
+}
+
+/// Reads an RLE or plaintext pattern file.
+pub fn load(path: &str) -> Result<Pattern, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path, e).into())
+}
+
+/// Plaintext (.cells) files are nothing but `!` comments and rows of
+/// `.`/`O`, anything else is treated as RLE.
+pub fn parse(text: &str) -> Result<Pattern, Box<dyn std::error::Error>> {
+    let is_plaintext = text
+        .lines()
+        .all(|l| l.starts_with('!') || l.trim_end().chars().all(|c| matches!(c, '.' | 'O' | '*')));
+
+    if is_plaintext {
+        parse_plaintext(text)
+    } else {
+        parse_rle(text)
+    }
+}
+
+/// Parses the plaintext `.cells` format.
+pub fn parse_plaintext(text: &str) -> Result<Pattern, Box<dyn std::error::Error>> {
+    let mut pattern = Pattern::default();
+    let mut row = 0;
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix('!') {
+            if let Some(name) = comment.trim().strip_prefix("Name:") {
+                pattern.name = Some(name.trim().to_string());
+            }
+            continue;
+        }
+        for (col, c) in line.trim_end().chars().enumerate() {
+            match c {
+                '.' => (),
+                'O' | '*' => pattern.cells.push((col as i32, -row, 0)),
+                other => return Err(format!("unexpected {:?} in plaintext pattern", other).into()),
+            }
+        }
+        row += 1;
+    }
+
+    Ok(pattern)
+}
+
+/// Parses run length encoded patterns, including the `x = ...` header.
+/// `/` starts the next layer up in z, see [`Pattern::to_rle`]. Under a
+/// Generations rule `A` is alive and later letters are dying cells, which
+/// a pattern has no room for, so they're left out.
+pub fn parse_rle(text: &str) -> Result<Pattern, Box<dyn std::error::Error>> {
+    let mut pattern = Pattern::default();
+    let mut body = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(name) = comment.strip_prefix('N') {
+                pattern.name = Some(name.trim().to_string());
+            }
+        } else if line.starts_with('x') && body.is_empty() {
+            for field in line.split(',') {
+                let mut kv = field.splitn(2, '=');
+                let key = kv.next().unwrap_or_default().trim();
+                let value = kv.next().unwrap_or_default().trim();
+                if key == "rule" {
+                    pattern.rule = Some(AnyRule::parse(value)?);
+                }
+            }
+        } else {
+            body.push_str(line);
+        }
+    }
+
+    let generations = matches!(pattern.rule, Some(AnyRule::Generations(_)));
+    let dying = |c: char| generations && c.is_ascii_uppercase() && c != 'A';
+    let (mut x, mut y, mut z) = (0, 0, 0);
+    let mut count = String::new();
+    for c in body.chars() {
+        let run = if c.is_ascii_digit() {
+            count.push(c);
+            continue;
+        } else if count.is_empty() {
+            1
+        } else {
+            count.parse::<i32>()?
+        };
+        count.clear();
+
+        match c {
+            'b' | '.' => x += run,
+            '$' => {
+                y -= run;
+                x = 0;
+            }
+            '/' => {
+                z += run;
+                y = 0;
+                x = 0;
+            }
+            '!' => break,
+            c if dying(c) => x += run,
+            c if c.is_ascii_alphabetic() => {
+                for _ in 0..run {
+                    pattern.cells.push((x, y, z));
+                    x += 1;
+                }
+            }
+            c if c.is_whitespace() => (),
+            other => return Err(format!("unexpected {:?} in RLE pattern", other).into()),
+        }
+    }
+
+    Ok(pattern)
+}
+
+impl Pattern {
+    /// The live cells of `game`, sorted.
+    pub fn from_game(game: &World<Rule>) -> Pattern {
+        Pattern {
+            rule: Some(game.rule.into()),
+            ..Pattern::from_world(game)
+        }
+    }
+
+    /// Every non-quiescent cell of `world`, sorted.
+    pub fn from_world<A: Automaton>(world: &World<A>) -> Pattern {
+        let mut cells = world.live_cells();
+        cells.sort_unstable();
+
+        Pattern {
+            cells,
+            rule: None,
+            name: None,
+        }
+    }
+
+    /// A copy with every cell moved through `symmetry`, around the origin.
+    pub fn transformed(&self, symmetry: Symmetry) -> Pattern {
+        self.map_cells(|c| symmetry.apply(c))
+    }
+
+    /// A copy moved by `offset`.
+    pub fn translated(&self, offset: Pos) -> Pattern {
+        self.map_cells(|c| (c.0 + offset.0, c.1 + offset.1, c.2 + offset.2))
+    }
+
+    /// A copy with the minimum corner of its bounds moved to the origin and
+    /// the cells sorted, so equal shapes compare equal.
+    pub fn normalized(&self) -> Pattern {
+        let (min, _) = self.bounds().unwrap_or_default();
+        let mut out = self.translated((-min.0, -min.1, -min.2));
+        out.cells.sort_unstable();
+        out.cells.dedup();
+        out
+    }
+
+    /// Every distinct orientation of the pattern, normalized. Planar
+    /// symmetries only unless `three_d` is set.
+    pub fn orientations(&self, three_d: bool) -> Vec<Pattern> {
+        let symmetries: Vec<Symmetry> = if three_d {
+            Symmetry::all().collect()
+        } else {
+            Symmetry::planar().collect()
+        };
+
+        let mut seen = HashSet::new();
+        let mut out = vec![];
+        for s in symmetries {
+            let p = self.transformed(s).normalized();
+            if seen.insert(p.cells.clone()) {
+                out.push(p);
+            }
+        }
+        out
+    }
+
+    /// Cells in either pattern.
+    pub fn union(&self, other: &Pattern) -> Pattern {
+        self.combine(other, |a, b| a | b)
+    }
+
+    /// Cells in both patterns.
+    pub fn intersection(&self, other: &Pattern) -> Pattern {
+        self.combine(other, |a, b| a & b)
+    }
+
+    /// Cells in `self` but not in `other`.
+    pub fn difference(&self, other: &Pattern) -> Pattern {
+        self.combine(other, |a, b| a & !b)
+    }
+
+    /// Cells in exactly one of the patterns.
+    pub fn symmetric_difference(&self, other: &Pattern) -> Pattern {
+        self.combine(other, |a, b| a ^ b)
+    }
+
+    /// Draws the pattern onto `world` with its origin at `at`, without
+    /// counting a generation. Returns the cells that changed.
+    pub fn stamp<A>(&self, world: &mut World<A>, at: Pos, mode: PasteMode) -> Diff
+    where
+        A: Automaton<State = bool>,
+    {
+        let cells: HashSet<Pos> = self.translated(at).cells.into_iter().collect();
+        let edits: Vec<(Pos, bool)> = match mode {
+            PasteMode::Or => cells.iter().map(|c| (*c, true)).collect(),
+            PasteMode::Xor => cells.iter().map(|c| (*c, !world.get(*c))).collect(),
+            PasteMode::AndNot => cells.iter().map(|c| (*c, false)).collect(),
+            PasteMode::Copy => match super::bounds(cells.iter().copied()) {
+                Some((min, max)) => world
+                    .cells_in(min, max)
+                    .filter(|(c, _)| !cells.contains(c))
+                    .map(|(c, _)| (c, false))
+                    .chain(cells.iter().map(|c| (*c, true)))
+                    .collect(),
+                None => vec![],
+            },
+        };
+
+        world.edit(edits)
+    }
+
+    fn map_cells(&self, f: impl Fn(Pos) -> Pos) -> Pattern {
+        Pattern {
+            cells: self.cells.iter().map(|c| f(*c)).collect(),
+            ..self.clone()
+        }
+    }
+
+    // keeps the rule and name of `self`, the result is sorted
+    fn combine(&self, other: &Pattern, keep: impl Fn(bool, bool) -> bool) -> Pattern {
+        let a: BTreeSet<Pos> = self.cells.iter().copied().collect();
+        let b: BTreeSet<Pos> = other.cells.iter().copied().collect();
+        let cells = a
+            .union(&b)
+            .filter(|c| keep(a.contains(c), b.contains(c)))
+            .copied()
+            .collect();
+
+        Pattern {
+            cells,
+            rule: self.rule,
+            name: self.name.clone(),
+        }
+    }
+
+    /// Inclusive (min, max) corners, None for an empty pattern.
+    pub fn bounds(&self) -> Option<(Pos, Pos)> {
+        super::bounds(self.cells.iter().copied())
+    }
+
+    /// Encodes the pattern as RLE with a header line. Patterns more than
+    /// one cell deep get a `z = ...` header field and their layers
+    /// separated by `/`, bottom first, which [`parse_rle`] reads back.
+    pub fn to_rle(&self) -> String {
+        let ((min_x, min_y, min_z), (max_x, max_y, max_z)) =
+            self.bounds().unwrap_or(((0, 0, 0), (-1, -1, 0)));
+        let mut out = String::new();
+        if let Some(name) = &self.name {
+            out.push_str(&format!("#N {}\n", name));
+        }
+        let depth = if max_z > min_z {
+            format!(", z = {}", max_z - min_z + 1)
+        } else {
+            String::new()
+        };
+        out.push_str(&format!(
+            "x = {}, y = {}{}, rule = {}\n",
+            max_x - min_x + 1,
+            max_y - min_y + 1,
+            depth,
+            self.rule.unwrap_or_default()
+        ));
+
+        let live: HashSet<Pos> = self.cells.iter().copied().collect();
+        let mut tokens: Vec<(usize, char)> = vec![];
+        let push = |tokens: &mut Vec<(usize, char)>, n: usize, c: char| match tokens.last_mut() {
+            Some((m, last)) if *last == c => *m += n,
+            _ => tokens.push((n, c)),
+        };
+
+        for z in min_z..=max_z {
+            // rows go from the top of the board down
+            for y in (min_y..=max_y).rev() {
+                let mut dead = 0;
+                for x in min_x..=max_x {
+                    if live.contains(&(x, y, z)) {
+                        if dead > 0 {
+                            push(&mut tokens, dead, 'b');
+                            dead = 0;
+                        }
+                        push(&mut tokens, 1, 'o');
+                    } else {
+                        dead += 1;
+                    }
+                }
+                if y != min_y {
+                    push(&mut tokens, 1, '$');
+                }
+            }
+            if z != max_z {
+                push(&mut tokens, 1, '/');
+            }
+        }
+        tokens.push((1, '!'));
+
+        let mut line = String::new();
+        for (n, c) in tokens {
+            let token = if n == 1 {
+                c.to_string()
+            } else {
+                format!("{}{}", n, c)
+            };
+            if line.len() + token.len() > 70 {
+                out.push_str(&line);
+                out.push('\n');
+                line.clear();
+            }
+            line.push_str(&token);
+        }
+        out.push_str(&line);
+        out.push('\n');
+
+        out
+    }
+
+    /// Writes the pattern as RLE.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, self.to_rle())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::generations::Generations;
+    use crate::game::library;
+    use crate::game::Game;
+
+    fn sorted(mut cells: Vec<Pos>) -> Vec<Pos> {
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn parses_rle_with_header() {
+        let pattern = parse("#N Glider\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n").unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.rule, Some(AnyRule::Life(Rule::CONWAY)));
+        assert_eq!(
+            sorted(pattern.cells),
+            vec![(0, -2, 0), (1, -2, 0), (1, 0, 0), (2, -2, 0), (2, -1, 0)]
+        );
+    }
+
+    #[test]
+    fn parses_plaintext() {
+        let pattern = parse("!Name: Blinker\n.O.\n.O.\n.O.\n").unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("Blinker"));
+        assert_eq!(
+            sorted(pattern.cells),
+            vec![(1, -2, 0), (1, -1, 0), (1, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_rle() {
+        assert!(parse("x = 1, y = 1, rule = B3/S23/xyz\no!").is_err());
+        assert!(parse("x = 2, y = 1\no?o!").is_err());
+    }
+
+    #[test]
+    fn names_what_failed_to_load() {
+        let err = load("no/such/pattern.rle").unwrap_err().to_string();
+        assert!(err.starts_with("no/such/pattern.rle: "), "{}", err);
+        let err = library::load("no-such-pattern").unwrap_err().to_string();
+        assert!(err.contains("no-such-pattern"), "{}", err);
+    }
+
+    #[test]
+    fn round_trips_every_library_pattern() {
+        for entry in library::PATTERNS {
+            let pattern = entry.pattern().normalized();
+            let back = parse(&pattern.to_rle()).unwrap();
+            assert_eq!(back.rule, pattern.rule, "{}", entry.name);
+            assert_eq!(back.name, pattern.name, "{}", entry.name);
+            assert_eq!(back.normalized().cells, pattern.cells, "{}", entry.name);
+        }
+    }
+
+    #[test]
+    fn keeps_layers_of_3d_patterns() {
+        let pattern = Pattern {
+            cells: vec![(0, 0, 0), (1, 0, 2), (0, -1, 2)],
+            ..Default::default()
+        };
+        let rle = pattern.to_rle();
+        assert!(rle.starts_with("x = 2, y = 2, z = 3,"), "{}", rle);
+        assert_eq!(
+            parse(&rle).unwrap().normalized().cells,
+            pattern.normalized().cells
+        );
+    }
+
+    #[test]
+    fn skips_dying_cells_of_generations_patterns() {
+        let pattern = parse("x = 4, y = 1, rule = B2/S/C3\nAB.A!").unwrap();
+        assert_eq!(
+            pattern.rule,
+            Some(AnyRule::Generations(Generations::BRIANS_BRAIN))
+        );
+        assert_eq!(sorted(pattern.cells), vec![(0, 0, 0), (3, 0, 0)]);
+    }
+
+    #[test]
+    fn symmetries_form_a_group() {
+        let all: Vec<Symmetry> = Symmetry::all().collect();
+        assert_eq!(all.len(), 48);
+        assert_eq!(all.iter().collect::<HashSet<_>>().len(), 48);
+        assert_eq!(all.iter().filter(|s| s.is_rotation()).count(), 24);
+        assert_eq!(Symmetry::planar().count(), 8);
+        for s in &all {
+            assert_eq!(s.then(s.inverse()), Symmetry::IDENTITY, "{:?}", s);
+            assert_eq!(s.inverse().then(*s), Symmetry::IDENTITY, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn symmetries_compose_in_order() {
+        let p = (1, 2, 3);
+        let s = Symmetry::ROTATE_Z.then(Symmetry::FLIP_X);
+        assert_eq!(
+            s.apply(p),
+            Symmetry::FLIP_X.apply(Symmetry::ROTATE_Z.apply(p))
+        );
+        let quarter = Symmetry::ROTATE_Z;
+        let full = quarter.then(quarter).then(quarter).then(quarter);
+        assert_eq!(full, Symmetry::IDENTITY);
+        assert_ne!(quarter.then(quarter), Symmetry::IDENTITY);
+    }
+
+    #[test]
+    fn counts_glider_orientations() {
+        let glider = library::get("glider").unwrap().pattern();
+        assert_eq!(glider.orientations(false).len(), 8);
+        assert_eq!(glider.orientations(true).len(), 24);
+        // the block looks the same every way round, but can face 3 planes
+        let block = parse("2o$2o!").unwrap();
+        assert_eq!(block.orientations(false).len(), 1);
+        assert_eq!(block.orientations(true).len(), 3);
+    }
+
+    #[test]
+    fn stamps_with_each_paste_mode() {
+        // two cells with a gap, landing on x = 10 and x = 12
+        let stamp = parse("obo!").unwrap();
+        let board = || Game::with_cells([(11, 0, 0), (12, 0, 0), (20, 0, 0)], Rule::CONWAY);
+        let after = |mode| {
+            let mut game = board();
+            stamp.stamp(&mut game, (10, 0, 0), mode);
+            sorted(game.live_cells())
+        };
+
+        assert_eq!(
+            after(PasteMode::Or),
+            [(10, 0, 0), (11, 0, 0), (12, 0, 0), (20, 0, 0)]
+        );
+        assert_eq!(after(PasteMode::Xor), [(10, 0, 0), (11, 0, 0), (20, 0, 0)]);
+        assert_eq!(after(PasteMode::AndNot), [(11, 0, 0), (20, 0, 0)]);
+        // the gap inside the stamp's box is cleared, the far cell isn't
+        assert_eq!(after(PasteMode::Copy), [(10, 0, 0), (12, 0, 0), (20, 0, 0)]);
+    }
+}
@@ -0,0 +1,304 @@
+//! Outer-totalistic birth/survival rules.
+
+use std::fmt;
+
+use super::automaton::{Automaton, MOORE_2D, MOORE_3D};
+use super::generations::Generations;
+use super::Pos;
+
+/// Which cells count towards a cell's neighbors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Neighborhood {
+    /// The 8 surrounding cells in the plane.
+    #[default]
+    Moore2D,
+    /// The 26 surrounding cells in space.
+    Moore3D,
+}
+
+impl Neighborhood {
+    /// Offsets from a cell to each of its neighbors.
+    pub fn offsets(self) -> &'static [Pos] {
+        match self {
+            Neighborhood::Moore2D => &MOORE_2D,
+            Neighborhood::Moore3D => &MOORE_3D,
+        }
+    }
+}
+
+/// A birth/survival rule. Bit n of each mask means "n live neighbors".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub birth: u32,
+    pub survive: u32,
+    pub neighborhood: Neighborhood,
+}
+
+impl Rule {
+    /// B3/S23
+    pub const CONWAY: Rule = Rule {
+        birth: 1 << 3,
+        survive: 1 << 2 | 1 << 3,
+        neighborhood: Neighborhood::Moore2D,
+    };
+
+    /// Bays' 3D Life 4555, B5/S45 on the 26 cell neighborhood.
+    pub const LIFE_4555: Rule = Rule {
+        birth: 1 << 5,
+        survive: 1 << 4 | 1 << 5,
+        neighborhood: Neighborhood::Moore3D,
+    };
+
+    /// Bays' 3D Life 5766, B6/S567 on the 26 cell neighborhood.
+    pub const LIFE_5766: Rule = Rule {
+        birth: 1 << 6,
+        survive: 1 << 5 | 1 << 6 | 1 << 7,
+        neighborhood: Neighborhood::Moore3D,
+    };
+
+    /// Whether a dead cell with this many neighbors comes alive.
+    pub fn born(&self, neighbors: u32) -> bool {
+        self.birth & (1 << neighbors) != 0
+    }
+
+    /// Whether a live cell with this many neighbors stays alive.
+    pub fn survives(&self, neighbors: u32) -> bool {
+        self.survive & (1 << neighbors) != 0
+    }
+
+    /// Accepts "B3/S23", "b3s23" and the old "23/3" survive/birth notation.
+    /// A "/3D" suffix switches to the 3D neighborhood, where counts above 9
+    /// are written comma separated, e.g. "B5/S4,5,10/3D".
+    pub fn parse(s: &str) -> Result<Rule, Box<dyn std::error::Error>> {
+        let s = s.trim();
+        let mut lower = s.to_ascii_lowercase();
+        let mut neighborhood = Neighborhood::Moore2D;
+        if let Some(flat) = lower.strip_suffix("/3d") {
+            lower = flat.to_string();
+            neighborhood = Neighborhood::Moore3D;
+        }
+
+        let invalid = || format!("invalid rule {:?}", s);
+        let (birth, survive) = if lower.starts_with('b') || lower.starts_with('s') {
+            let mut birth = None;
+            let mut survive = None;
+            // each run of digits belongs to the letter in front of it
+            let mut current: Option<char> = None;
+            let mut digits = String::new();
+            for c in lower.chars().chain(std::iter::once('/')) {
+                match c {
+                    'b' | 's' | '/' => {
+                        let section = match current {
+                            Some('b') => Some(&mut birth),
+                            Some('s') => Some(&mut survive),
+                            _ => None,
+                        };
+                        match section {
+                            Some(section @ None) => *section = Some(std::mem::take(&mut digits)),
+                            // "B3S23S45"
+                            Some(Some(_)) => return Err(invalid().into()),
+                            None => {}
+                        }
+                        current = if c == '/' { None } else { Some(c) };
+                    }
+                    // anything after a bare '/', like the "C3" of a
+                    // Generations rule
+                    _ if current.is_none() => return Err(invalid().into()),
+                    _ => digits.push(c),
+                }
+            }
+            (birth.unwrap_or_default(), survive.unwrap_or_default())
+        } else {
+            match lower.split('/').collect::<Vec<_>>().as_slice() {
+                [survive, birth] => (birth.to_string(), survive.to_string()),
+                _ => return Err(invalid().into()),
+            }
+        };
+
+        Ok(Rule {
+            birth: digits_to_mask(&birth).ok_or_else(invalid)?,
+            survive: digits_to_mask(&survive).ok_or_else(invalid)?,
+            neighborhood,
+        })
+    }
+}
+
+/// Either kind of rule a pattern file or the command line can name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnyRule {
+    Life(Rule),
+    Generations(Generations),
+}
+
+impl AnyRule {
+    /// Accepts whatever [`Rule::parse`] or [`Generations::parse`] does.
+    pub fn parse(s: &str) -> Result<AnyRule, Box<dyn std::error::Error>> {
+        match Rule::parse(s) {
+            Ok(rule) => Ok(AnyRule::Life(rule)),
+            Err(e) => Generations::parse(s)
+                .map(AnyRule::Generations)
+                .map_err(|_| e),
+        }
+    }
+}
+
+impl Default for AnyRule {
+    fn default() -> AnyRule {
+        AnyRule::Life(Rule::default())
+    }
+}
+
+impl From<Rule> for AnyRule {
+    fn from(rule: Rule) -> AnyRule {
+        AnyRule::Life(rule)
+    }
+}
+
+impl From<Generations> for AnyRule {
+    fn from(rule: Generations) -> AnyRule {
+        AnyRule::Generations(rule)
+    }
+}
+
+impl fmt::Display for AnyRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnyRule::Life(rule) => rule.fmt(f),
+            AnyRule::Generations(rule) => rule.fmt(f),
+        }
+    }
+}
+
+fn digits_to_mask(digits: &str) -> Option<u32> {
+    let mut mask = 0u32;
+    if digits.contains(',') {
+        for n in digits.split(',') {
+            mask |= 1u32.checked_shl(n.trim().parse().ok()?)?;
+        }
+    } else {
+        for c in digits.chars() {
+            mask |= 1 << c.to_digit(10)?;
+        }
+    }
+    Some(mask)
+}
+
+fn write_counts(f: &mut fmt::Formatter<'_>, mask: u32) -> fmt::Result {
+    let counts: Vec<String> = (0..32)
+        .filter(|n| mask & (1 << n) != 0)
+        .map(|n: u32| n.to_string())
+        .collect();
+    if mask >> 10 != 0 {
+        write!(f, "{}", counts.join(","))
+    } else {
+        write!(f, "{}", counts.concat())
+    }
+}
+
+impl Automaton for Rule {
+    type State = bool;
+
+    fn neighborhood(&self) -> &[Pos] {
+        self.neighborhood.offsets()
+    }
+
+    fn transition(&self, alive: bool, neighbors: &[bool]) -> bool {
+        let n = neighbors.iter().filter(|n| **n).count() as u32;
+        if alive {
+            self.survives(n)
+        } else {
+            self.born(n)
+        }
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Rule {
+        Rule::CONWAY
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "B")?;
+        write_counts(f, self.birth)?;
+        write!(f, "/S")?;
+        write_counts(f, self.survive)?;
+        if self.neighborhood == Neighborhood::Moore3D {
+            write!(f, "/3D")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_both_notations() {
+        for s in ["B3/S23", "b3s23", "S23B3", " B3/S23 ", "23/3"] {
+            assert_eq!(Rule::parse(s).unwrap(), Rule::CONWAY, "{}", s);
+        }
+        assert_eq!(Rule::parse("B5/S45/3D").unwrap(), Rule::LIFE_4555);
+        let empty = Rule::parse("B2/S").unwrap();
+        assert_eq!((empty.birth, empty.survive), (1 << 2, 0));
+    }
+
+    #[test]
+    fn parses_counts_above_nine() {
+        let rule = Rule::parse("B5/S4,5,10/3D").unwrap();
+        assert_eq!(rule.survive, 1 << 4 | 1 << 5 | 1 << 10);
+        assert_eq!(rule.to_string(), "B5/S4,5,10/3D");
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        for s in [
+            "B3/S23/C3",
+            "B3/S23/xyz",
+            "B3S23S45",
+            "B3/B4/S23",
+            "B3x/S23",
+            "23/3/1",
+            "23",
+            "B3/S2a",
+        ] {
+            assert!(Rule::parse(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn parses_generations_rules_as_any_rule() {
+        assert_eq!(
+            AnyRule::parse("B2/S/C3").unwrap(),
+            AnyRule::Generations(Generations::BRIANS_BRAIN)
+        );
+        assert_eq!(
+            AnyRule::parse("B3/S23").unwrap(),
+            AnyRule::Life(Rule::CONWAY)
+        );
+        assert!(AnyRule::parse("B3/S23/xyz").is_err());
+        let rule = AnyRule::parse("B5/S45/3D/C4").unwrap();
+        assert_eq!(AnyRule::parse(&rule.to_string()).unwrap(), rule);
+        assert!(matches!(rule, AnyRule::Generations(g) if g.neighborhood == Neighborhood::Moore3D));
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        for rule in [Rule::CONWAY, Rule::LIFE_4555, Rule::LIFE_5766] {
+            assert_eq!(Rule::parse(&rule.to_string()).unwrap(), rule);
+        }
+    }
+
+    #[test]
+    fn follows_birth_and_survival() {
+        let rule = Rule::CONWAY;
+        let neighbors =
+            |n: usize| [[true; 8].as_slice()[..n].to_vec(), vec![false; 8 - n]].concat();
+        assert!(rule.transition(false, &neighbors(3)));
+        assert!(!rule.transition(false, &neighbors(2)));
+        assert!(rule.transition(true, &neighbors(2)));
+        assert!(!rule.transition(true, &neighbors(4)));
+    }
+}
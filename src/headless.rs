@@ -0,0 +1,163 @@
+use std::time::Instant;
+
+use game_of_life_3d::game::rule::{AnyRule, Rule};
+use game_of_life_3d::game::{library, pattern, Game};
+use game_of_life_3d::recorder::Recorder;
+
+pub const USAGE: &str = "usage: game_of_life_3d run <pattern> [options]
+
+<pattern> is an .rle or .cells file, or the name of a built-in pattern
+(see `game_of_life_3d list`).
+
+options:
+  -g, --generations <n>   generations to run (default 100)
+  -r, --rule <rule>       rule to run with, e.g. B3/S23 (default: the pattern's own rule, or B3/S23)
+  -o, --out <file.rle>    write the final pattern here (default: stdout)
+      --stats <file>      write per-generation stats as CSV, or JSON Lines for .jsonl
+";
+
+// exit codes
+pub const OK: i32 = 0;
+pub const FAILED: i32 = 1;
+pub const BAD_USAGE: i32 = 2;
+
+struct Options {
+    pattern: String,
+    generations: u64,
+    rule: Option<AnyRule>,
+    out: Option<String>,
+    stats: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut pattern = None;
+    let mut opts = Options {
+        pattern: String::new(),
+        generations: 100,
+        rule: None,
+        out: None,
+        stats: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "-g" | "--generations" => {
+                opts.generations = value()?
+                    .parse()
+                    .map_err(|e| format!("bad generation count: {}", e))?
+            }
+            "-r" | "--rule" => {
+                opts.rule = Some(AnyRule::parse(&value()?).map_err(|e| e.to_string())?)
+            }
+            "-o" | "--out" => opts.out = Some(value()?),
+            "--stats" => opts.stats = Some(value()?),
+            other if other.starts_with('-') => return Err(format!("unknown option {}", other)),
+            other if pattern.is_none() => pattern = Some(other.to_string()),
+            other => return Err(format!("unexpected argument {}", other)),
+        }
+    }
+
+    opts.pattern = pattern.ok_or("no pattern given")?;
+    Ok(opts)
+}
+
+// prints the built-in patterns
+pub fn list() {
+    for e in library::PATTERNS {
+        let period = e.period.map(|p| format!("p{}", p)).unwrap_or_default();
+        println!(
+            "{:<20} {:<12} {:<11} {:<4} {}",
+            e.name,
+            e.rule.to_string(),
+            format!("{:?}", e.kind).to_lowercase(),
+            period,
+            e.description
+        );
+    }
+}
+
+// runs the simulation without touching winit or wgpu, returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    let opts = match parse_args(args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return BAD_USAGE;
+        }
+    };
+
+    match simulate(&opts) {
+        Ok(()) => OK,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            FAILED
+        }
+    }
+}
+
+fn simulate(opts: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let start = library::load(&opts.pattern)?;
+    let rule: Rule = match opts.rule.or(start.rule).unwrap_or_default() {
+        AnyRule::Life(rule) => rule,
+        // the output pattern would lose which cells are dying
+        AnyRule::Generations(rule) => {
+            return Err(format!(
+                "{} is a Generations rule, those only run in the viewer",
+                rule
+            )
+            .into())
+        }
+    };
+    let mut game = Game::with_cells(start.cells.iter().copied(), rule);
+
+    let mut recorder = Recorder::new(0);
+    if let Some(path) = &opts.stats {
+        recorder.stream_to_file(path)?;
+    }
+
+    let (mut births, mut deaths) = (0, 0);
+    let begin = Instant::now();
+    for _ in 0..opts.generations {
+        let step_start = Instant::now();
+        let diff = game.update();
+        recorder.record(&game, &diff, step_start.elapsed());
+        births += diff.born().count();
+        deaths += diff.died().count();
+    }
+    let elapsed = begin.elapsed();
+    recorder.stop_stream()?;
+
+    let mut end = pattern::Pattern::from_game(&game);
+    end.name = start.name;
+    match &opts.out {
+        Some(path) => end.save(path)?,
+        None => print!("{}", end.to_rle()),
+    }
+
+    // stats go to stderr so stdout stays a valid pattern
+    eprintln!("rule: {}", rule);
+    eprintln!("generations: {}", game.generation());
+    eprintln!(
+        "population: {} (started with {})",
+        end.cells.len(),
+        start.cells.len()
+    );
+    eprintln!("births: {}, deaths: {}", births, deaths);
+    match end.bounds() {
+        Some((min, max)) => eprintln!("bounds: {:?}..{:?}", min, max),
+        None => eprintln!("bounds: empty"),
+    }
+    eprintln!(
+        "elapsed: {:.3}s ({:.0} generations/s)",
+        elapsed.as_secs_f64(),
+        opts.generations as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,576 @@
+//! Per-instance data the renderer draws a cell mesh with.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::{Range, RangeBounds};
+
+use crate::game::automaton::{Appearance, Automaton, VON_NEUMANN_3D};
+use crate::game::{Diff, Pos, World};
+use crate::voxel::chunk_of;
+
+/// How the renderer packs instances into its buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// The whole model matrix and color, see [`Instance::to_raw`]. Needed
+    /// for rotated cells.
+    Matrix,
+    /// Integer position, RGBA8 color and scale, see [`Instance::to_compact`].
+    #[default]
+    Compact,
+}
+
+impl Layout {
+    /// Bytes per instance.
+    pub fn stride(self) -> usize {
+        match self {
+            Layout::Matrix => Instance::RAW_LEN * std::mem::size_of::<f32>(),
+            Layout::Compact => Instance::COMPACT_LEN,
+        }
+    }
+
+    /// Writes `instance` into `out`, which is `stride()` bytes long.
+    pub fn encode(self, instance: &Instance, out: &mut [u8]) {
+        match self {
+            Layout::Matrix => {
+                for (bytes, f) in out.chunks_exact_mut(4).zip(instance.to_raw()) {
+                    bytes.copy_from_slice(&f.to_le_bytes());
+                }
+            }
+            Layout::Compact => out.copy_from_slice(&instance.to_compact()),
+        }
+    }
+}
+
+/// Where one copy of the cell mesh goes and how it looks.
+pub struct Instance {
+    pub position: na::Point3<f32>,
+    pub rotation: na::UnitQuaternion<f32>,
+    pub scale: f32,
+    pub color: [f32; 4],
+}
+
+impl Instance {
+    /// Floats per instance in [`Instance::to_raw`].
+    pub const RAW_LEN: usize = 16 + 4;
+
+    /// Bytes per instance in [`Instance::to_compact`].
+    pub const COMPACT_LEN: usize = 12 + 4 + 4;
+
+    /// An unrotated cell at `pos`.
+    pub fn for_cell(pos: Pos, look: Appearance) -> Instance {
+        Instance {
+            position: na::Point3::new(pos.0 as f32, pos.1 as f32, pos.2 as f32),
+            rotation: na::UnitQuaternion::identity(),
+            scale: look.scale,
+            color: look.color,
+        }
+    }
+
+    /// The model matrix as 16 column-major floats, then the RGBA color.
+    pub fn to_raw(&self) -> Vec<f32> {
+        let a = na::Similarity3::from_parts(
+            na::Translation3::from(self.position.coords),
+            self.rotation,
+            self.scale,
+        )
+        .to_homogeneous();
+        let mut raw = a.as_slice().to_vec();
+        raw.extend_from_slice(&self.color);
+        raw
+    }
+
+    /// The position rounded to three little endian i32s, the color as RGBA8
+    /// and the scale as an f32. Rotation is dropped.
+    pub fn to_compact(&self) -> [u8; Instance::COMPACT_LEN] {
+        let mut out = [0; Instance::COMPACT_LEN];
+        for (i, c) in self.position.coords.iter().enumerate() {
+            out[i * 4..i * 4 + 4].copy_from_slice(&(c.round() as i32).to_le_bytes());
+        }
+        for (i, c) in self.color.iter().enumerate() {
+            out[12 + i] = (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        out[16..].copy_from_slice(&self.scale.to_le_bytes());
+        out
+    }
+}
+
+/// How many instances a buffer holding `current` should be regrown to so
+/// `needed` fit: doubling until they do, but never past `max`. Returns
+/// `current` if it's already big enough.
+pub fn grow_capacity(current: usize, needed: usize, max: usize) -> usize {
+    if needed <= current {
+        return current;
+    }
+    let mut capacity = current.max(1);
+    while capacity < needed {
+        capacity = capacity.saturating_mul(2);
+    }
+    capacity.min(max).max(current)
+}
+
+/// Instance data for every live cell, packed in some [`Layout`] and kept up
+/// to date from diffs, so only the slots that changed need uploading.
+///
+/// Slots come in pages of [`PAGE`], each page holding cells of a single
+/// chunk (see [`chunk_of`]) drawn with a single mesh (see
+/// [`Appearance::mesh`]), so chunks can be drawn or skipped on their own and
+/// each mesh drawn separately with [`InstanceTable::ranges`]. Each cell keeps
+/// its slot until it dies or changes mesh, dead slots are zeroed (which draws
+/// nothing) and reused by the next birth in that chunk with that mesh. A
+/// group's pages go back to the table once it's empty, and groups or the
+/// whole table get repacked once they're mostly holes.
+///
+/// With `hide_interior` set, cells whose six face neighbors are all alive
+/// are left out since they're boxed in. That's only invisible with a cell
+/// mesh that fills its whole cell, otherwise they show through the gaps.
+#[derive(Debug, Default)]
+pub struct InstanceTable {
+    layout: Layout,
+    hide_interior: bool,
+    data: Vec<u8>,
+    slots: HashMap<Pos, usize>,
+    owners: Vec<Option<Pos>>,
+    // the group each page belongs to
+    pages: Vec<Option<Group>>,
+    groups: HashMap<Group, GroupSlots>,
+    free_pages: Vec<usize>,
+    dirty: BTreeSet<usize>,
+}
+
+/// Slots per page of an [`InstanceTable`].
+pub const PAGE: usize = 16;
+
+// a chunk and a mesh, the cells that share pages
+type Group = (Pos, usize);
+
+#[derive(Debug, Default)]
+struct GroupSlots {
+    pages: Vec<usize>,
+    free: Vec<usize>,
+    cells: usize,
+}
+
+impl InstanceTable {
+    // don't bother compacting small tables
+    const MIN_COMPACT: usize = 16;
+    // clean slots worth uploading to save a separate write
+    const MERGE_GAP: usize = 16;
+
+    /// A table holding every visible cell of `world`, all of it dirty.
+    pub fn from_world<A: Automaton>(
+        world: &World<A>,
+        layout: Layout,
+        hide_interior: bool,
+    ) -> InstanceTable {
+        let mut table = InstanceTable {
+            layout,
+            hide_interior,
+            ..Default::default()
+        };
+        for (pos, state) in world.cells() {
+            if !hide_interior || !enclosed(world, pos) {
+                table.write(pos, world.rule.appearance(state));
+            }
+        }
+        table
+    }
+
+    /// Slots in use, holes included.
+    pub fn len(&self) -> usize {
+        self.owners.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+
+    /// Cells in the table, hidden ones aren't counted.
+    pub fn population(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn hides_interior(&self) -> bool {
+        self.hide_interior
+    }
+
+    /// Every slot, `layout().stride()` bytes each.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Chunks with cells in the table.
+    pub fn chunks(&self) -> impl Iterator<Item = Pos> + '_ {
+        let chunks: HashSet<Pos> = self.groups.keys().map(|g| g.0).collect();
+        chunks.into_iter()
+    }
+
+    /// The slots holding cells of `chunks` drawn with one of `meshes`, as
+    /// sorted and merged instance ranges to draw.
+    pub fn ranges(
+        &self,
+        chunks: impl IntoIterator<Item = Pos>,
+        meshes: impl RangeBounds<usize>,
+    ) -> Vec<Range<u32>> {
+        let chunks: HashSet<Pos> = chunks.into_iter().collect();
+        let mut pages: Vec<usize> = self
+            .groups
+            .iter()
+            .filter(|(g, _)| chunks.contains(&g.0) && meshes.contains(&g.1))
+            .flat_map(|(_, slots)| slots.pages.iter().copied())
+            .collect();
+        pages.sort_unstable();
+
+        let mut ranges: Vec<Range<u32>> = vec![];
+        for page in pages {
+            let slots = (page * PAGE) as u32..((page + 1) * PAGE) as u32;
+            match ranges.last_mut() {
+                Some(range) if range.end == slots.start => range.end = slots.end,
+                _ => ranges.push(slots),
+            }
+        }
+        ranges
+    }
+
+    /// [`InstanceTable::ranges`] for each of the first `meshes` meshes, one
+    /// draw's worth each. The last one also gets the cells meant for meshes
+    /// past it.
+    pub fn ranges_by_mesh(
+        &self,
+        chunks: impl IntoIterator<Item = Pos>,
+        meshes: usize,
+    ) -> Vec<Vec<Range<u32>>> {
+        let chunks: Vec<Pos> = chunks.into_iter().collect();
+        (0..meshes)
+            .map(|i| {
+                if i + 1 == meshes {
+                    self.ranges(chunks.iter().copied(), i..)
+                } else {
+                    self.ranges(chunks.iter().copied(), i..=i)
+                }
+            })
+            .collect()
+    }
+
+    /// Brings the table in line with a change to `world`, meant to be
+    /// called from a [`World::subscribe`] observer.
+    pub fn patch<A: Automaton>(&mut self, world: &World<A>, diff: &Diff<A::State>) {
+        if !self.hide_interior {
+            for c in diff.changes.iter() {
+                if c.after == A::State::default() {
+                    self.remove(c.pos);
+                } else {
+                    self.write(c.pos, world.rule.appearance(c.after));
+                }
+            }
+        } else {
+            // a change can also cover or uncover the cells next to it
+            let changed: HashSet<Pos> = diff.changes.iter().map(|c| c.pos).collect();
+            let mut affected = changed.clone();
+            for p in changed.iter() {
+                for o in VON_NEUMANN_3D.iter() {
+                    affected.insert((p.0 + o.0, p.1 + o.1, p.2 + o.2));
+                }
+            }
+
+            for p in affected {
+                let state = world.get(p);
+                let visible = state != A::State::default() && !enclosed(world, p);
+                match (visible, self.slots.contains_key(&p)) {
+                    (false, true) => self.remove(p),
+                    (true, false) => self.write(p, world.rule.appearance(state)),
+                    (true, true) if changed.contains(&p) => {
+                        self.write(p, world.rule.appearance(state))
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        let mut touched: HashSet<Group> = HashSet::new();
+        for c in diff.changes.iter() {
+            for state in [c.before, c.after] {
+                if state != A::State::default() {
+                    touched.insert((chunk_of(c.pos), world.rule.appearance(state).mesh));
+                }
+            }
+        }
+        for group in touched {
+            let sparse = self
+                .groups
+                .get(&group)
+                .is_some_and(|g| g.pages.len() * PAGE > 2 * g.cells + PAGE);
+            if sparse {
+                self.repack(group);
+            }
+        }
+
+        let free = self.free_pages.len();
+        if free > InstanceTable::MIN_COMPACT && 2 * free > self.pages.len() {
+            self.compact();
+        }
+    }
+
+    /// Slot ranges changed since the last call, merged into runs. Runs can
+    /// include a few unchanged slots to keep their number down.
+    pub fn take_dirty(&mut self) -> Vec<Range<usize>> {
+        let mut runs: Vec<Range<usize>> = vec![];
+        for slot in std::mem::take(&mut self.dirty) {
+            match runs.last_mut() {
+                Some(run) if slot - run.end <= InstanceTable::MERGE_GAP => run.end = slot + 1,
+                _ => runs.push(slot..slot + 1),
+            }
+        }
+        runs
+    }
+
+    /// Marks every slot dirty, for when the GPU copy is lost or replaced.
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty = (0..self.len()).collect();
+    }
+
+    fn slot_bytes(&mut self, slot: usize) -> &mut [u8] {
+        let stride = self.layout.stride();
+        &mut self.data[slot * stride..(slot + 1) * stride]
+    }
+
+    // a free slot in the cell's group, taking another page if it's full
+    fn alloc(&mut self, pos: Pos, mesh: usize) -> usize {
+        let group = (chunk_of(pos), mesh);
+        let entry = self.groups.entry(group).or_default();
+        if entry.free.is_empty() {
+            let page = self.free_pages.pop().unwrap_or_else(|| {
+                self.pages.push(None);
+                self.owners.resize(self.pages.len() * PAGE, None);
+                self.data
+                    .resize(self.owners.len() * self.layout.stride(), 0);
+                // the GPU copy may still have cells from before a compaction
+                let page = self.pages.len() - 1;
+                self.dirty.extend(page * PAGE..(page + 1) * PAGE);
+                page
+            });
+            self.pages[page] = Some(group);
+            entry.pages.push(page);
+            entry.free.extend((page * PAGE..(page + 1) * PAGE).rev());
+        }
+
+        let slot = entry.free.pop().unwrap();
+        entry.cells += 1;
+        self.slots.insert(pos, slot);
+        self.owners[slot] = Some(pos);
+        slot
+    }
+
+    fn write(&mut self, pos: Pos, look: Appearance) {
+        let slot = match self.slots.get(&pos).copied() {
+            Some(slot) if self.pages[slot / PAGE].is_some_and(|g| g.1 == look.mesh) => slot,
+            Some(_) => {
+                self.remove(pos);
+                self.alloc(pos, look.mesh)
+            }
+            None => self.alloc(pos, look.mesh),
+        };
+
+        let layout = self.layout;
+        layout.encode(&Instance::for_cell(pos, look), self.slot_bytes(slot));
+        self.dirty.insert(slot);
+    }
+
+    fn remove(&mut self, pos: Pos) {
+        let Some(slot) = self.slots.remove(&pos) else {
+            return;
+        };
+        self.owners[slot] = None;
+        self.slot_bytes(slot).fill(0);
+        self.dirty.insert(slot);
+
+        let group = self.pages[slot / PAGE].unwrap();
+        let entry = self.groups.get_mut(&group).unwrap();
+        entry.cells -= 1;
+        entry.free.push(slot);
+        if entry.cells == 0 {
+            for page in self.groups.remove(&group).unwrap().pages {
+                self.pages[page] = None;
+                self.free_pages.push(page);
+            }
+        }
+    }
+
+    // takes every cell out of `pages`, which are left zeroed
+    fn evict(&mut self, pages: &[usize]) -> Vec<(Pos, usize, Vec<u8>)> {
+        let mut cells = vec![];
+        for page in pages {
+            let Some((_, mesh)) = self.pages[*page] else {
+                continue;
+            };
+            for slot in page * PAGE..(page + 1) * PAGE {
+                if let Some(pos) = self.owners[slot].take() {
+                    self.slots.remove(&pos);
+                    cells.push((pos, mesh, self.slot_bytes(slot).to_vec()));
+                    self.slot_bytes(slot).fill(0);
+                    self.dirty.insert(slot);
+                }
+            }
+        }
+        cells
+    }
+
+    fn place(&mut self, cells: Vec<(Pos, usize, Vec<u8>)>) {
+        for (pos, mesh, bytes) in cells {
+            let slot = self.alloc(pos, mesh);
+            self.slot_bytes(slot).copy_from_slice(&bytes);
+            self.dirty.insert(slot);
+        }
+    }
+
+    // moves a group's cells into as few pages as they fit in
+    fn repack(&mut self, group: Group) {
+        let Some(entry) = self.groups.remove(&group) else {
+            return;
+        };
+        let cells = self.evict(&entry.pages);
+        for page in entry.pages {
+            self.pages[page] = None;
+            self.free_pages.push(page);
+        }
+        self.place(cells);
+    }
+
+    // lays every cell out again from the start of the table
+    fn compact(&mut self) {
+        let all: Vec<usize> = (0..self.pages.len()).collect();
+        let cells = self.evict(&all);
+        self.pages.clear();
+        self.groups.clear();
+        self.free_pages.clear();
+        self.owners.clear();
+        self.data.clear();
+        self.dirty.clear();
+        self.place(cells);
+    }
+}
+
+// all six face neighbors are alive
+fn enclosed<A: Automaton>(world: &World<A>, pos: Pos) -> bool {
+    VON_NEUMANN_3D
+        .iter()
+        .all(|o| world.get((pos.0 + o.0, pos.1 + o.1, pos.2 + o.2)) != A::State::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::generations::Generations;
+    use crate::game::rule::Rule;
+
+    fn slots(ranges: &[Range<u32>]) -> usize {
+        ranges.iter().map(|r| r.len()).sum()
+    }
+
+    // every cell's slot holds its instance and every other slot is zeroed
+    fn check<A: Automaton>(table: &InstanceTable, world: &World<A>) {
+        let stride = table.layout().stride();
+        let mut expected = vec![0; table.data().len()];
+        for (pos, slot) in table.slots.iter() {
+            let look = world.rule.appearance(world.get(*pos));
+            let bytes = &mut expected[slot * stride..(slot + 1) * stride];
+            table
+                .layout()
+                .encode(&Instance::for_cell(*pos, look), bytes);
+        }
+        assert_eq!(table.data(), expected.as_slice());
+        assert_eq!(table.population(), world.population());
+    }
+
+    #[test]
+    fn reuses_slots_after_removals() {
+        let mut world = World::with_cells([(0, 0, 0), (1, 0, 0), (2, 0, 0)], Rule::CONWAY);
+        let mut table = InstanceTable::from_world(&world, Layout::default(), false);
+        table.take_dirty();
+        let len = table.len();
+        let slot = table.slots[&(1, 0, 0)];
+
+        let diff = world.edit([((1, 0, 0), false)]);
+        table.patch(&world, &diff);
+        check(&table, &world);
+        assert_eq!(table.take_dirty(), vec![slot..slot + 1]);
+
+        let diff = world.edit([((3, 0, 0), true)]);
+        table.patch(&world, &diff);
+        check(&table, &world);
+        assert_eq!(table.slots[&(3, 0, 0)], slot);
+        assert_eq!(table.len(), len);
+    }
+
+    #[test]
+    fn patches_keep_up_with_the_board() {
+        for layout in [Layout::Matrix, Layout::Compact] {
+            let mut world = crate::game::Game::new();
+            let mut table = InstanceTable::from_world(&world, layout, false);
+            for _ in 0..200 {
+                let diff = world.update();
+                table.patch(&world, &diff);
+            }
+            check(&table, &world);
+            // holes get repacked, so it's never mostly empty
+            assert!(table.len() <= 2 * world.population() + table.groups.len() * PAGE);
+        }
+    }
+
+    #[test]
+    fn hides_only_enclosed_cells() {
+        let mut cube = vec![];
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    cube.push((x, y, z));
+                }
+            }
+        }
+        let mut world = World::with_cells(cube, Rule::LIFE_4555);
+        let table = InstanceTable::from_world(&world, Layout::default(), true);
+        assert_eq!(table.population(), 26);
+        assert!(!table.slots.contains_key(&(1, 1, 1)));
+
+        // opening a face uncovers the middle
+        let mut table = table;
+        let diff = world.edit([((1, 1, 0), false)]);
+        table.patch(&world, &diff);
+        assert!(table.slots.contains_key(&(1, 1, 1)));
+        assert_eq!(table.population(), 26);
+    }
+
+    #[test]
+    fn generations_fill_a_draw_per_mesh() {
+        // a Brian's Brain blinker leaves dying cells behind every step
+        let mut world = World::with_cells([(0, 0, 0), (1, 0, 0)], Generations::BRIANS_BRAIN);
+        world.update();
+        let states: HashSet<u8> = world.cells().map(|(_, s)| s).collect();
+        assert_eq!(states, HashSet::from([1, 2]));
+
+        let table = InstanceTable::from_world(&world, Layout::default(), false);
+        let chunks: Vec<Pos> = table.chunks().collect();
+        let draws = table.ranges_by_mesh(chunks.iter().copied(), 2);
+        assert!(draws.iter().all(|d| !d.is_empty()));
+        let mut covered: Vec<u32> = draws.iter().flatten().flat_map(|r| r.clone()).collect();
+        covered.sort_unstable();
+        let before = covered.len();
+        covered.dedup();
+        assert_eq!(covered.len(), before, "a slot is drawn twice");
+
+        // with a single mesh everything lands in its draw
+        let draws = table.ranges_by_mesh(chunks.iter().copied(), 1);
+        assert_eq!(slots(&draws[0]), before);
+    }
+
+    #[test]
+    fn life_uses_one_mesh() {
+        let world = World::with_cells([(0, 0, 0), (1, 0, 0), (2, 0, 0)], Rule::CONWAY);
+        let table = InstanceTable::from_world(&world, Layout::default(), false);
+        let draws = table.ranges_by_mesh(table.chunks(), 3);
+        assert!(!draws[0].is_empty());
+        assert!(draws[1..].iter().all(|d| d.is_empty()));
+    }
+}
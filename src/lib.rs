@@ -0,0 +1,27 @@
+//! A Game of Life simulation that can be driven without a window.
+//!
+//! [`game::Game`] holds the board and steps it with a [`game::rule::Rule`],
+//! [`game::pattern`] reads and writes RLE and plaintext patterns, and
+//! [`camera::Camera`] has the view math the viewer uses. None of it needs
+//! winit or wgpu, build with `default-features = false` to leave them out.
+//!
+//! ```
+//! use game_of_life_3d::game::{pattern, rule::Rule, Game};
+//!
+//! let glider = pattern::parse("bo$2bo$3o!").unwrap();
+//! let mut game = Game::with_cells(glider.cells, Rule::CONWAY);
+//! for _ in 0..4 {
+//!     game.update();
+//! }
+//! assert_eq!(game.live_cells().len(), 5);
+//! ```
+
+extern crate nalgebra as na;
+
+pub mod camera;
+pub mod clock;
+pub mod game;
+pub mod instance;
+pub mod recorder;
+pub mod timeline;
+pub mod voxel;
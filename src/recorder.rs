@@ -0,0 +1,326 @@
+//! Per-generation population statistics.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::game::automaton::Automaton;
+use crate::game::{Diff, Pos, World};
+
+/// How samples are written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    JsonLines,
+}
+
+impl Format {
+    /// JSON Lines for `.jsonl`/`.json`, CSV for anything else.
+    pub fn from_path(path: &str) -> Format {
+        if path.ends_with(".jsonl") || path.ends_with(".json") {
+            Format::JsonLines
+        } else {
+            Format::Csv
+        }
+    }
+}
+
+/// What happened in one generation.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub generation: u64,
+    pub population: usize,
+    pub births: usize,
+    pub deaths: usize,
+    /// (min, max) of the live cells, None when the board is empty.
+    pub bounds: Option<(Pos, Pos)>,
+    pub step_time: Duration,
+}
+
+const CSV_HEADER: &str =
+    "generation,population,births,deaths,min_x,min_y,min_z,max_x,max_y,max_z,step_us";
+
+impl Sample {
+    fn write(&self, out: &mut dyn Write, format: Format) -> io::Result<()> {
+        let us = self.step_time.as_micros();
+        match (format, self.bounds) {
+            (Format::Csv, Some((min, max))) => writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{},{}",
+                self.generation,
+                self.population,
+                self.births,
+                self.deaths,
+                min.0,
+                min.1,
+                min.2,
+                max.0,
+                max.1,
+                max.2,
+                us
+            ),
+            (Format::Csv, None) => writeln!(
+                out,
+                "{},{},{},{},,,,,,,{}",
+                self.generation, self.population, self.births, self.deaths, us
+            ),
+            (Format::JsonLines, bounds) => {
+                let bounds = match bounds {
+                    Some((min, max)) => format!(
+                        "[[{},{},{}],[{},{},{}]]",
+                        min.0, min.1, min.2, max.0, max.1, max.2
+                    ),
+                    None => "null".to_string(),
+                };
+                writeln!(
+                    out,
+                    "{{\"generation\":{},\"population\":{},\"births\":{},\"deaths\":{},\"bounds\":{},\"step_us\":{}}}",
+                    self.generation, self.population, self.births, self.deaths, bounds, us
+                )
+            }
+        }
+    }
+}
+
+/// Keeps the last `capacity` samples and optionally streams every sample
+/// to a writer as it's recorded. A failed write closes the stream, the
+/// error comes back from [`Recorder::stop_stream`].
+pub struct Recorder {
+    capacity: usize,
+    samples: VecDeque<Sample>,
+    stream: Option<(Box<dyn Write + Send + Sync>, Format)>,
+    // the write that closed the stream
+    error: Option<io::Error>,
+}
+
+impl std::fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("capacity", &self.capacity)
+            .field("samples", &self.samples.len())
+            .field("streaming", &self.stream.is_some())
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl Recorder {
+    pub fn new(capacity: usize) -> Recorder {
+        Recorder {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+            stream: None,
+            error: None,
+        }
+    }
+
+    /// Call right after [`World::update`] with the diff it returned.
+    pub fn record<A: Automaton>(
+        &mut self,
+        game: &World<A>,
+        diff: &Diff<A::State>,
+        step_time: Duration,
+    ) {
+        let sample = Sample {
+            generation: game.generation(),
+            population: game.population(),
+            births: diff.born().count(),
+            deaths: diff.died().count(),
+            bounds: game.bounds(),
+            step_time,
+        };
+
+        if let Some((out, format)) = self.stream.as_mut() {
+            if let Err(e) = sample.write(out.as_mut(), *format) {
+                log::warn!("recorder stream failed, closing it: {}", e);
+                self.stream = None;
+                self.error = Some(e);
+            }
+        }
+
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Dumps everything still in the ring buffer.
+    pub fn write_to(&self, out: &mut dyn Write, format: Format) -> io::Result<()> {
+        if format == Format::Csv {
+            writeln!(out, "{}", CSV_HEADER)?;
+        }
+        for s in self.samples.iter() {
+            s.write(out, format)?;
+        }
+        out.flush()
+    }
+
+    /// Writes the ring buffer to a file, the format going by its extension.
+    pub fn export(&self, path: &str) -> io::Result<()> {
+        let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut file, Format::from_path(path))
+    }
+
+    /// Every sample recorded from now on is also written to `out`.
+    pub fn stream_to(
+        &mut self,
+        mut out: Box<dyn Write + Send + Sync>,
+        format: Format,
+    ) -> io::Result<()> {
+        if format == Format::Csv {
+            writeln!(out, "{}", CSV_HEADER)?;
+        }
+        self.stream = Some((out, format));
+        self.error = None;
+        Ok(())
+    }
+
+    /// Streams to a file, the format going by its extension.
+    pub fn stream_to_file(&mut self, path: &str) -> io::Result<()> {
+        let file = io::LineWriter::new(std::fs::File::create(path)?);
+        self.stream_to(Box::new(file), Format::from_path(path))
+    }
+
+    /// Flushes and closes the stream. Fails if this or any write since
+    /// [`Recorder::stream_to`] did.
+    pub fn stop_stream(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        match self.stream.take() {
+            Some((mut out, _)) => out.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::rule::Rule;
+    use crate::game::Game;
+    use std::sync::{Arc, Mutex};
+
+    // a blinker, which turns over two cells every generation
+    fn blinker() -> Game {
+        Game::with_cells([(0, 0, 0), (1, 0, 0), (2, 0, 0)], Rule::CONWAY)
+    }
+
+    fn run(recorder: &mut Recorder, game: &mut Game, generations: usize) {
+        for _ in 0..generations {
+            let diff = game.update();
+            recorder.record(game, &diff, Duration::from_micros(5));
+        }
+    }
+
+    fn written(recorder: &Recorder, format: Format) -> String {
+        let mut out = vec![];
+        recorder.write_to(&mut out, format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_csv_and_json_lines() {
+        let mut recorder = Recorder::new(10);
+        let mut game = blinker();
+        run(&mut recorder, &mut game, 1);
+        game.edit([((1, -1, 0), false), ((1, 0, 0), false), ((1, 1, 0), false)]);
+        run(&mut recorder, &mut game, 1);
+
+        assert_eq!(
+            written(&recorder, Format::Csv),
+            format!("{}\n1,3,2,2,1,-1,0,1,1,0,5\n2,0,0,0,,,,,,,5\n", CSV_HEADER)
+        );
+        assert_eq!(
+            written(&recorder, Format::JsonLines),
+            "{\"generation\":1,\"population\":3,\"births\":2,\"deaths\":2,\
+             \"bounds\":[[1,-1,0],[1,1,0]],\"step_us\":5}\n\
+             {\"generation\":2,\"population\":0,\"births\":0,\"deaths\":0,\
+             \"bounds\":null,\"step_us\":5}\n"
+        );
+    }
+
+    #[test]
+    fn keeps_only_the_last_samples() {
+        let mut recorder = Recorder::new(3);
+        run(&mut recorder, &mut blinker(), 5);
+        let csv = written(&recorder, Format::Csv);
+        let generations: Vec<&str> = csv
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap())
+            .collect();
+        assert_eq!(generations, ["3", "4", "5"]);
+
+        let mut none = Recorder::new(0);
+        run(&mut none, &mut blinker(), 5);
+        assert_eq!(written(&none, Format::Csv), format!("{}\n", CSV_HEADER));
+    }
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streams_every_sample() {
+        let mut recorder = Recorder::new(1);
+        let out = Shared::default();
+        recorder
+            .stream_to(Box::new(out.clone()), Format::Csv)
+            .unwrap();
+        run(&mut recorder, &mut blinker(), 3);
+        recorder.stop_stream().unwrap();
+        assert!(!recorder.is_streaming());
+
+        let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(text.lines().count(), 4);
+        assert!(text.starts_with(CSV_HEADER));
+    }
+
+    #[test]
+    fn reports_a_failed_stream() {
+        let mut recorder = Recorder::new(10);
+        recorder
+            .stream_to(Box::new(Full), Format::JsonLines)
+            .unwrap();
+        run(&mut recorder, &mut blinker(), 2);
+        assert!(!recorder.is_streaming());
+        // the ring buffer keeps recording regardless
+        assert_eq!(written(&recorder, Format::JsonLines).lines().count(), 2);
+
+        let err = recorder.stop_stream().unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+        // reported once, the next stream starts clean
+        assert!(recorder.stop_stream().is_ok());
+    }
+}
@@ -1,5 +1,8 @@
 struct VertexInput {
 	[[location(0)]] position: vec3<f32>;
+	[[location(1)]] normal: vec3<f32>;
+	[[location(2)]] uv: vec2<f32>;
+	[[location(3)]] color: vec4<f32>;
 };
 
 struct InstanceTransform {
@@ -7,10 +10,27 @@ struct InstanceTransform {
   [[location(11)]] b: vec4<f32>;
   [[location(12)]] c: vec4<f32>;
   [[location(13)]] d: vec4<f32>;
+  [[location(14)]] color: vec4<f32>;
+};
+
+struct CompactInstance {
+  [[location(10)]] position: vec3<i32>;
+  [[location(11)]] color: vec4<f32>;
+  [[location(12)]] scale: f32;
+};
+
+struct VoxelVertex {
+	[[location(0)]] position: vec3<f32>;
+	[[location(1)]] normal: vec3<f32>;
+	[[location(2)]] color: vec4<f32>;
 };
 
 struct VertexOutput {
 	[[builtin(position)]] position: vec4<f32>;
+	[[location(0)]] color: vec4<f32>;
+	[[location(1)]] world_position: vec3<f32>;
+	[[location(2)]] normal: vec3<f32>;
+	[[location(3)]] uv: vec2<f32>;
 };
 
 struct RotUniform {
@@ -19,26 +39,112 @@ struct RotUniform {
 struct CamUniform {
 	rot: mat4x4<f32>;
 };
+// direction points towards the light, color.w scales the highlight and
+// ambient.w is its shininess
+struct Light {
+	direction: vec4<f32>;
+	color: vec4<f32>;
+	ambient: vec4<f32>;
+	eye: vec4<f32>;
+};
 
 [[group(0), binding(0)]]
 var<uniform> rot: RotUniform;
 [[group(0), binding(1)]]
 var<uniform> camera: CamUniform;
+[[group(0), binding(2)]]
+var<uniform> light: Light;
+
+struct MaterialUniform {
+	emissive: vec4<f32>;
+};
+
+[[group(1), binding(0)]]
+var base_color: texture_2d<f32>;
+[[group(1), binding(1)]]
+var base_sampler: sampler;
+[[group(1), binding(2)]]
+var<uniform> material: MaterialUniform;
 
 [[stage(vertex)]]
 fn vs_main(in: VertexInput, instance: InstanceTransform) -> VertexOutput {
 	var out: VertexOutput;
+	out.color = instance.color * in.color;
+	// empty slots are all zeros, put them behind the far plane
+	if (instance.d.w == 0.0) {
+		out.position = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+		return out;
+	}
 	var transform = mat4x4<f32>(
 		instance.a,
 		instance.b,
 		instance.c,
 		instance.d,
 	);
-	out.position = camera.rot * transform * vec4<f32>(in.position.xyz, 3.0);
+	let world = transform * vec4<f32>(in.position.xyz, 3.0);
+	out.position = camera.rot * world;
+	out.world_position = world.xyz / world.w;
+	out.normal = (transform * vec4<f32>(in.normal, 0.0)).xyz;
+	out.uv = in.uv;
+	return out;
+}
+
+// same placement as vs_main's matrix, which draws with w = 3
+[[stage(vertex)]]
+fn vs_compact(in: VertexInput, instance: CompactInstance) -> VertexOutput {
+	var out: VertexOutput;
+	out.color = instance.color * in.color;
+	if (instance.scale == 0.0) {
+		out.position = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+		return out;
+	}
+	let offset = vec3<f32>(instance.position) * 3.0;
+	let world = vec4<f32>(in.position.xyz * instance.scale + offset, 3.0);
+	out.position = camera.rot * world;
+	out.world_position = world.xyz / world.w;
+	out.normal = in.normal;
+	out.uv = in.uv;
+	return out;
+}
+
+// chunk meshes are already in board coordinates
+[[stage(vertex)]]
+fn vs_voxel(in: VoxelVertex) -> VertexOutput {
+	var out: VertexOutput;
+	out.position = camera.rot * vec4<f32>(in.position, 1.0);
+	out.color = in.color;
+	out.world_position = in.position;
+	out.normal = in.normal;
+	out.uv = vec2<f32>(0.0);
 	return out;
 }
 
+// Lambert diffuse plus a Blinn-Phong highlight
+fn shade(color: vec4<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> vec4<f32> {
+	let n = normalize(normal);
+	let l = normalize(light.direction.xyz);
+	let v = normalize(light.eye.xyz - world_position);
+	let diffuse = max(dot(n, l), 0.0);
+	var specular = 0.0;
+	if (diffuse > 0.0) {
+		let h = normalize(l + v);
+		specular = pow(max(dot(n, h), 0.0), light.ambient.w) * light.color.w;
+	}
+	let lit = color.rgb * (light.ambient.rgb + light.color.rgb * diffuse)
+		+ light.color.rgb * specular;
+	return vec4<f32>(lit, color.a);
+}
+
+// the cell mesh, with its material
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+	let color = in.color * textureSample(base_color, base_sampler, in.uv);
+	let lit = shade(color, in.world_position, in.normal);
+	return vec4<f32>(lit.rgb + material.emissive.rgb, lit.a);
+}
+
+// chunk meshes and points, which have no texture coordinates
 [[stage(fragment)]]
-fn fs_main([[builtin(position)]] coord: vec4<f32>) -> [[location(0)]] vec4<f32> {
-  return vec4<f32>(coord.xyz, 1.0);
+fn fs_untextured(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+	return shade(in.color, in.world_position, in.normal);
 }
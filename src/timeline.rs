@@ -0,0 +1,39 @@
+//! Playback direction and bookmarks for scrubbing through history.
+
+/// Which generation the viewer plays next: forwards or backwards through
+/// history, optionally looping between two bookmarks.
+#[derive(Debug, Default)]
+pub struct Timeline {
+    pub reverse: bool,
+    pub looping: bool,
+    pub bookmarks: [Option<u64>; 2],
+    /// Digits typed so far for a jump.
+    pub typed: String,
+}
+
+impl Timeline {
+    /// The bookmarked range, if both are set and looping is on.
+    pub fn loop_range(&self) -> Option<(u64, u64)> {
+        match self.bookmarks {
+            [Some(a), Some(b)] if self.looping => Some((a.min(b), a.max(b))),
+            _ => None,
+        }
+    }
+
+    /// The generation to show after `current` on the next tick, None when
+    /// playing backwards from generation 0.
+    pub fn next(&self, current: u64) -> Option<u64> {
+        let range = self.loop_range();
+        if self.reverse {
+            match range {
+                Some((lo, hi)) if current <= lo || current > hi => Some(hi),
+                _ => current.checked_sub(1),
+            }
+        } else {
+            match range {
+                Some((lo, hi)) if current >= hi || current < lo => Some(lo),
+                _ => Some(current + 1),
+            }
+        }
+    }
+}
@@ -0,0 +1,1059 @@
+//! The window: draws the board with wgpu and drives it from winit events.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use game_of_life_3d::camera::{Camera, Frustum};
+use game_of_life_3d::clock::Clock;
+use game_of_life_3d::game::automaton::Automaton;
+use game_of_life_3d::game::rule::AnyRule;
+use game_of_life_3d::game::{
+    components::Connectivity, history::History, pattern::Pattern, Pos, World,
+};
+use game_of_life_3d::instance::{grow_capacity, InstanceTable, Layout};
+use game_of_life_3d::recorder::Recorder;
+use game_of_life_3d::timeline::Timeline;
+use game_of_life_3d::voxel::{self, ChunkMesh, Chunks};
+use log::{info, warn};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroupEntry, BindingType, BufferUsages, Color, CommandEncoderDescriptor, DeviceDescriptor,
+    Operations, RenderPassDescriptor, RequestAdapterOptions, SurfaceConfiguration,
+};
+use winit::{
+    event::{self, Event, WindowEvent},
+    event_loop::ControlFlow,
+};
+
+use crate::headless;
+
+mod model;
+
+// chunks further than this from the camera are drawn in less detail
+const LOD_DISTANCE: f32 = 48.0;
+
+// the cell mesh when GOL_MODEL isn't set, if it's there
+const DEFAULT_MODEL: &str = "./alexisbox.gltf";
+
+// instance buffer memory when GOL_INSTANCE_MEMORY (in MiB) isn't set
+const DEFAULT_INSTANCE_MEMORY: usize = 256;
+
+// generation history memory when GOL_HISTORY_MEMORY (in MiB) isn't set
+const DEFAULT_HISTORY_MEMORY: usize = 64;
+
+// keyframes kept every this many generations
+const KEYFRAME_INTERVAL: u64 = 64;
+
+/// How the board gets drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    /// The cell model once per live cell.
+    Instances,
+    /// Greedy meshed chunks, far fewer triangles for dense 3D boards.
+    Voxels,
+}
+
+/// The shader's light uniform, a directional light plus ambient.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck_derive::Pod, bytemuck_derive::Zeroable)]
+struct Light {
+    /// Towards the light.
+    direction: [f32; 4],
+    /// w is how strong the highlight is.
+    color: [f32; 4],
+    /// w is the highlight's shininess.
+    ambient: [f32; 4],
+    /// The camera, for the highlight.
+    eye: [f32; 4],
+}
+
+impl Light {
+    // from above and a little to the side, so the faces of a cell differ
+    const SUN: Light = Light {
+        direction: [0.4, 1.0, 0.3, 0.0],
+        color: [0.8, 0.8, 0.75, 0.3],
+        ambient: [0.25, 0.25, 0.3, 32.0],
+        eye: [0.0; 4],
+    };
+}
+
+#[derive(Debug)]
+struct MeshBuffers {
+    vertices: wgpu::Buffer,
+    indices: wgpu::Buffer,
+    count: u32,
+}
+
+// every pipeline the renderers use, rebuilt only when the instance layout
+// or surface format they were built for changes
+#[derive(Debug)]
+struct Pipelines {
+    instances: Layout,
+    format: wgpu::TextureFormat,
+    cells: wgpu::RenderPipeline,
+    points: wgpu::RenderPipeline,
+    voxels: wgpu::RenderPipeline,
+}
+
+impl Pipelines {
+    fn new<A: Automaton>(state: &RenderState<A>, instances: Layout) -> Pipelines {
+        Pipelines {
+            instances,
+            format: state.config.format,
+            cells: model::make_pipeline(state, instances).unwrap(),
+            points: model::make_point_pipeline(state, instances).unwrap(),
+            voxels: model::make_voxel_pipeline(state).unwrap(),
+        }
+    }
+
+    fn fits<A: Automaton>(&self, state: &RenderState<A>, instances: Layout) -> bool {
+        self.instances == instances && self.format == state.config.format
+    }
+}
+
+// a cell mesh, drawn once for the cells of each state that use it
+#[derive(Debug)]
+struct CellMesh {
+    buffers: MeshBuffers,
+    material: wgpu::BindGroup,
+}
+
+// a chunk's greedy mesh and the box drawn in its place from far away
+#[derive(Debug)]
+struct ChunkBuffers {
+    full: MeshBuffers,
+    far: MeshBuffers,
+}
+
+#[derive(Debug)]
+pub struct RenderState<A: Automaton> {
+    window: winit::window::Window,
+    device: wgpu::Device,
+    meshes: Vec<CellMesh>,
+    // a single vertex at the origin facing up, for drawing instances as
+    // points
+    point_vbo: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    instances: Arc<Mutex<InstanceTable>>,
+    instance_capacity: usize,
+    // bytes the instance buffer may take up
+    instance_memory: usize,
+    // set while cells are being left out for lack of memory
+    capped: bool,
+    renderer: Renderer,
+    chunks: Arc<Mutex<Chunks>>,
+    chunk_buffers: HashMap<Pos, ChunkBuffers>,
+    queue: wgpu::Queue,
+    surface: wgpu::Surface,
+    config: SurfaceConfiguration,
+    depth: wgpu::TextureView,
+    bind_groups: Box<[wgpu::BindGroup]>,
+    bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    // built on the first frame
+    pipelines: Option<Pipelines>,
+    camera: Camera,
+    camera_buffer: wgpu::Buffer,
+    light: Light,
+    light_buffer: wgpu::Buffer,
+    game: World<A>,
+    recorder: Recorder,
+    history: History<A::State>,
+    timeline: Timeline,
+    delta: f32,
+    time: f32,
+}
+
+// the viewer's part of `--help`
+pub fn usage() -> String {
+    format!(
+        "usage: game_of_life_3d [pattern]\n       game_of_life_3d list\n\n\
+         GOL_MODEL=<mesh>[,...] the cell mesh, a glTF, OBJ, STL or PLY file or one of\n\
+         \x20   {}\n\
+         \x20   (default {} if it's there, otherwise a cube, or a quad for 2D patterns)\n\
+         \x20   with a list, each cell state gets the next mesh and later ones the last\n\
+         GOL_INSTANCE_MEMORY=<MiB> caps the viewer's instance buffer (default {})\n\
+         GOL_HISTORY_MEMORY=<MiB> caps the history stepped back through (default {})\n",
+        model::shapes::NAMES.join(", "),
+        DEFAULT_MODEL,
+        DEFAULT_INSTANCE_MEMORY,
+        DEFAULT_HISTORY_MEMORY,
+    )
+}
+
+// runs `start` with its own rule, or Conway's
+pub fn open(start: Pattern) {
+    match start.rule.unwrap_or_default() {
+        AnyRule::Life(rule) => view(World::with_cells(start.cells, rule)),
+        AnyRule::Generations(rule) => view(World::with_cells(start.cells, rule)),
+    }
+}
+
+// opens the window and runs `game` in it until it's closed
+fn view<A>(mut game: World<A>)
+where
+    A: Automaton + Send + Sync + 'static,
+{
+    let fallback = || {
+        if game.is_3d() {
+            model::shapes::cube()
+        } else {
+            model::shapes::quad()
+        }
+    };
+    // one mesh per Appearance::mesh
+    let models: Vec<model::Model> = match std::env::var("GOL_MODEL") {
+        Ok(names) => names
+            .split(',')
+            .map(|name| model::get(name.trim()))
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|e| {
+                eprintln!("couldn't load the cell mesh: {}", e);
+                std::process::exit(headless::FAILED);
+            }),
+        Err(_) if std::path::Path::new(DEFAULT_MODEL).exists() => {
+            vec![model::load(DEFAULT_MODEL).unwrap_or_else(|e| {
+                warn!(
+                    "couldn't load {}, using a generated mesh: {}",
+                    DEFAULT_MODEL, e
+                );
+                fallback()
+            })]
+        }
+        Err(_) => vec![fallback()],
+    };
+    let event_loop = winit::event_loop::EventLoop::new();
+    let window = winit::window::WindowBuilder::new()
+        .build(&event_loop)
+        .unwrap();
+    let id = window.id();
+
+    let size = window.inner_size();
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+
+    let adapter = futures::executor::block_on(instance.request_adapter(&RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::LowPower,
+        force_fallback_adapter: false,
+        compatible_surface: None,
+    }))
+    .unwrap();
+
+    let res = futures::executor::block_on(adapter.request_device(
+        &DeviceDescriptor {
+            label: Some("Main Device"),
+
+            ..Default::default()
+        },
+        None,
+    ));
+
+    let (device, queue) = match res {
+        Err(e) => panic!("{}", e.to_string()),
+        Ok(resp) => resp,
+    };
+
+    let surface = unsafe { instance.create_surface(&window) };
+    let config = SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: surface.get_preferred_format(&adapter).unwrap(),
+        width: size.width,
+        height: size.height,
+        present_mode: wgpu::PresentMode::Fifo,
+    };
+    surface.configure(&device, &config);
+    let depth = make_depth_view(&device, &config);
+
+    let material_layout = model::make_material_layout(&device);
+    let meshes = models
+        .iter()
+        .map(|model| CellMesh {
+            buffers: MeshBuffers {
+                vertices: device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("VBO"),
+                    contents: bytemuck::cast_slice(model.verts.as_slice()),
+                    usage: BufferUsages::VERTEX,
+                }),
+                indices: device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("VBI"),
+                    contents: bytemuck::cast_slice(model.indices.as_slice()),
+                    usage: BufferUsages::INDEX,
+                }),
+                count: model.indices.len() as u32,
+            },
+            material: model::make_material_bind_group(
+                &device,
+                &queue,
+                &material_layout,
+                &model.material,
+            ),
+        })
+        .collect();
+
+    let point_vbo = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Point VBO"),
+        contents: bytemuck::cast_slice(&[model::Vertex {
+            position: [0.0; 3],
+            normal: [0.0, 1.0, 0.0],
+            uv: [0.0; 2],
+            color: [1.0; 4],
+        }]),
+        usage: BufferUsages::VERTEX,
+    });
+
+    let bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    let cam_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: std::mem::size_of::<na::Matrix4<f32>>() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let light_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: std::mem::size_of::<Light>() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let camera = Camera {
+        pos: na::Point3::new(0.0, 4.0, -5.0),
+        target: na::Point3::new(0.0, 0.0, 0.0),
+        rot_x: 0.0,
+        rot_y: 0.0,
+    };
+
+    let bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bg_layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: device
+                    .create_buffer_init(&BufferInitDescriptor {
+                        label: None,
+                        contents: bytemuck::cast_slice(&[na::Matrix4::new_rotation(
+                            na::Vector3::y() * 90.0,
+                        ) * na::Matrix4::new_rotation(
+                            na::Vector3::x() * 45.0,
+                        )]),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    })
+                    .as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: cam_buf.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: light_buf.as_entire_binding(),
+            },
+        ],
+    });
+
+    // group 1 is each mesh's material, set as it's drawn
+    let bind_groups = Box::new([bg]);
+    let bind_group_layouts = vec![bg_layout, material_layout];
+
+    // the instance table follows the board through its diffs. Cells are
+    // drawn smaller than their cell, so enclosed ones show through the
+    // gaps and only get hidden when asked for with H
+    let instances = Arc::new(Mutex::new(InstanceTable::from_world(
+        &game,
+        Layout::default(),
+        false,
+    )));
+    let table = instances.clone();
+    game.subscribe(move |world, diff| table.lock().unwrap().patch(world, diff));
+    let chunks = Arc::new(Mutex::new(Chunks::from_world(&game)));
+    let dirty = chunks.clone();
+    game.subscribe(move |_, diff| dirty.lock().unwrap().mark(diff));
+
+    let history_memory = mib_from_env("GOL_HISTORY_MEMORY", DEFAULT_HISTORY_MEMORY);
+    let mut history = History::new(KEYFRAME_INTERVAL, history_memory * 1024 * 1024);
+    history.reset(&game);
+
+    let memory = mib_from_env("GOL_INSTANCE_MEMORY", DEFAULT_INSTANCE_MEMORY);
+    let instance_memory = memory * 1024 * 1024;
+    let (instance_buffer, instance_capacity) = {
+        let table = instances.lock().unwrap();
+        let max = max_instances(instance_memory, table.layout());
+        let capacity = grow_capacity(2048.min(max), table.len(), max);
+        (
+            make_instance_buffer(&device, capacity, table.layout()),
+            capacity,
+        )
+    };
+
+    let mut state_rc = Arc::new(RenderState {
+        device,
+        surface,
+        config,
+        depth,
+        meshes,
+        point_vbo,
+        bind_groups,
+        bind_group_layouts,
+        pipelines: None,
+        queue,
+        instance_buffer,
+        instances,
+        instance_capacity,
+        instance_memory,
+        capped: false,
+        renderer: Renderer::Instances,
+        chunks,
+        chunk_buffers: HashMap::new(),
+        window,
+        camera,
+        camera_buffer: cam_buf,
+        light: Light::SUN,
+        light_buffer: light_buf,
+        game,
+        recorder: Recorder::new(10_000),
+        history,
+        timeline: Timeline::default(),
+        delta: 0.0,
+        time: 0.0,
+    });
+
+    let (sx, rx) = std::sync::mpsc::channel();
+    let (key_sx, key_rx) = std::sync::mpsc::channel();
+    let (resize_sx, resize_rx) = std::sync::mpsc::channel();
+
+    let _thread = std::thread::spawn(move || {
+        let mut go = false;
+        if let Ok(yes) = rx.recv() {
+            go = yes;
+        }
+        let state = Arc::get_mut(&mut state_rc).unwrap();
+        let mut last_start = std::time::Instant::now();
+        let mut clock = Clock::default();
+        if go {
+            loop {
+                let now = std::time::Instant::now();
+                let delta = now - last_start;
+                clock.advance(delta, || state.tick());
+                state.delta = delta.as_secs_f32();
+                state.time += state.delta;
+                last_start = now;
+                // only the latest size matters
+                if let Some(size) = resize_rx.try_iter().last() {
+                    state.resize(size);
+                }
+                if let Ok(keycode) = key_rx.try_recv() {
+                    match keycode {
+                        event::VirtualKeyCode::W => state.camera.pos.z += 1.0,
+                        event::VirtualKeyCode::S => state.camera.pos.z -= 1.0,
+                        event::VirtualKeyCode::A => state.camera.pos.x += 1.0,
+                        event::VirtualKeyCode::D => state.camera.pos.x -= 1.0,
+                        event::VirtualKeyCode::Up => state.camera.rot_y += 1.0,
+                        event::VirtualKeyCode::Down => state.camera.rot_y -= 1.0,
+                        event::VirtualKeyCode::Left => state.camera.rot_x += 1.0,
+                        event::VirtualKeyCode::Right => state.camera.rot_x -= 1.0,
+                        event::VirtualKeyCode::Comma => {
+                            // a seek, so a keyframe still works once the
+                            // deltas back to it are gone
+                            let generation = state.game.generation();
+                            let back = generation.checked_sub(1);
+                            if !back.is_some_and(|g| state.history.seek(&mut state.game, g)) {
+                                info!("generation {} is as far back as history goes", generation)
+                            }
+                        }
+                        event::VirtualKeyCode::Period => state.step(),
+                        event::VirtualKeyCode::Space => {
+                            clock.toggle_pause();
+                            info!("paused: {}", clock.paused);
+                        }
+                        event::VirtualKeyCode::N => {
+                            let typed = std::mem::take(&mut state.timeline.typed);
+                            let n = typed.parse().unwrap_or(1);
+                            clock.queue(n);
+                            info!("stepping {} generations", clock.queued);
+                        }
+                        event::VirtualKeyCode::Equals
+                        | event::VirtualKeyCode::NumpadAdd
+                        | event::VirtualKeyCode::Minus
+                        | event::VirtualKeyCode::NumpadSubtract => {
+                            match keycode {
+                                event::VirtualKeyCode::Minus
+                                | event::VirtualKeyCode::NumpadSubtract => clock.slower(),
+                                _ => clock.faster(),
+                            }
+                            info!("{} generations per second", clock.rate);
+                        }
+                        event::VirtualKeyCode::X => {
+                            let (layout, hide) = {
+                                let table = state.instances.lock().unwrap();
+                                (table.layout(), table.hides_interior())
+                            };
+                            let layout = match layout {
+                                Layout::Compact => Layout::Matrix,
+                                Layout::Matrix => Layout::Compact,
+                            };
+                            state.rebuild_instances(layout, hide);
+                            info!("instance layout: {:?}", layout);
+                        }
+                        event::VirtualKeyCode::H => {
+                            let (layout, hide) = {
+                                let table = state.instances.lock().unwrap();
+                                (table.layout(), !table.hides_interior())
+                            };
+                            state.rebuild_instances(layout, hide);
+                            info!(
+                                "hiding enclosed cells: {}, drawing {} of {}",
+                                hide,
+                                state.instances.lock().unwrap().population(),
+                                state.game.population()
+                            );
+                        }
+                        event::VirtualKeyCode::V => {
+                            state.renderer = match state.renderer {
+                                Renderer::Instances => Renderer::Voxels,
+                                Renderer::Voxels => Renderer::Instances,
+                            };
+                            info!("drawing with {:?}", state.renderer);
+                        }
+                        event::VirtualKeyCode::M => {
+                            clock.max_speed = !clock.max_speed;
+                            info!("max speed: {}", clock.max_speed);
+                        }
+                        event::VirtualKeyCode::R => {
+                            state.timeline.reverse = !state.timeline.reverse;
+                            info!("playing in reverse: {}", state.timeline.reverse);
+                        }
+                        event::VirtualKeyCode::LBracket | event::VirtualKeyCode::RBracket => {
+                            let i = (keycode == event::VirtualKeyCode::RBracket) as usize;
+                            state.timeline.bookmarks[i] = Some(state.game.generation());
+                            info!("bookmarks: {:?}", state.timeline.bookmarks);
+                        }
+                        event::VirtualKeyCode::O => {
+                            state.timeline.looping = !state.timeline.looping;
+                            match state.timeline.loop_range() {
+                                Some((lo, hi)) => info!("looping {}..={}", lo, hi),
+                                None if state.timeline.looping => {
+                                    info!("looping once both bookmarks are set")
+                                }
+                                None => info!("stopped looping"),
+                            }
+                        }
+                        event::VirtualKeyCode::Home => {
+                            let oldest = state.history.oldest();
+                            state.goto(oldest);
+                        }
+                        event::VirtualKeyCode::End => {
+                            let newest = state.history.newest();
+                            state.goto(newest);
+                        }
+                        event::VirtualKeyCode::Back => state.timeline.typed.clear(),
+                        event::VirtualKeyCode::G => {
+                            let typed = std::mem::take(&mut state.timeline.typed);
+                            match typed.parse() {
+                                Ok(generation) if generation > state.history.newest() => {
+                                    // simulated over the next frames, all at once
+                                    // would freeze the window for a big jump
+                                    let newest = state.history.newest();
+                                    if state.goto(newest) {
+                                        clock.queue(generation - newest);
+                                        state.timeline.reverse = false;
+                                        if state.timeline.looping {
+                                            state.timeline.looping = false;
+                                            info!("stopped looping");
+                                        }
+                                        info!("stepping ahead to generation {}", generation)
+                                    }
+                                }
+                                Ok(generation) if state.goto(generation) => {
+                                    info!("jumped to generation {}", generation)
+                                }
+                                Ok(generation) => {
+                                    warn!("generation {} isn't in history any more", generation)
+                                }
+                                Err(_) => info!("type a generation number, then G"),
+                            }
+                        }
+                        event::VirtualKeyCode::E => match state.recorder.export("./population.csv")
+                        {
+                            Ok(_) => info!("wrote ./population.csv"),
+                            Err(e) => warn!("couldn't write ./population.csv: {}", e),
+                        },
+                        event::VirtualKeyCode::L => {
+                            if state.recorder.is_streaming() {
+                                match state.recorder.stop_stream() {
+                                    Ok(()) => info!("stopped streaming to ./population.jsonl"),
+                                    Err(e) => warn!("couldn't write ./population.jsonl: {}", e),
+                                }
+                            } else {
+                                // a failed write already closed the stream,
+                                // say why before starting a new one
+                                if let Err(e) = state.recorder.stop_stream() {
+                                    warn!("streaming to ./population.jsonl stopped early: {}", e);
+                                }
+                                match state.recorder.stream_to_file("./population.jsonl") {
+                                    Ok(()) => info!("streaming to ./population.jsonl"),
+                                    Err(e) => warn!("couldn't stream to ./population.jsonl: {}", e),
+                                }
+                            }
+                        }
+                        event::VirtualKeyCode::C | event::VirtualKeyCode::I => {
+                            let connectivity = match keycode {
+                                event::VirtualKeyCode::I => Connectivity::Island,
+                                _ => Connectivity::Moore,
+                            };
+                            let objects = state.game.components(connectivity);
+                            info!("{} objects on the board", objects.len());
+                            for (i, o) in objects.iter().enumerate() {
+                                info!(
+                                    "object {}: population {}, bounds {:?}..{:?}",
+                                    i,
+                                    o.population(),
+                                    o.min,
+                                    o.max
+                                );
+                            }
+                        }
+                        other => {
+                            if let Some(digit) = digit(other) {
+                                state.timeline.typed.push(digit);
+                            }
+                        }
+                    }
+                }
+
+                state.render();
+            }
+        }
+    });
+
+    event_loop.run(move |event, _, cf| {
+        // let state = Rc::get_mut(state_rc).unwrap();
+        sx.send(true).unwrap();
+        match event {
+            Event::WindowEvent {
+                window_id,
+                ref event,
+            } if window_id == id => match event {
+                WindowEvent::KeyboardInput { input, .. } => {
+                    if input.state != event::ElementState::Pressed {
+                        return;
+                    }
+                    if let Some(keycode) = input.virtual_keycode {
+                        key_sx.send(keycode).unwrap();
+                    }
+                }
+                WindowEvent::Resized(size) => resize_sx.send(*size).unwrap(),
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    resize_sx.send(**new_inner_size).unwrap()
+                }
+                WindowEvent::CloseRequested => *cf = ControlFlow::Exit,
+                _ => {}
+            },
+            Event::RedrawRequested(_id) => {
+                // state.render();
+                // state.window.request_redraw();
+            }
+            _ => {}
+        }
+    });
+}
+
+// a size in MiB from the environment, `default` if it's unset or bad
+fn mib_from_env(name: &str, default: usize) -> usize {
+    match std::env::var(name) {
+        Ok(mib) => mib.parse().unwrap_or_else(|_| {
+            warn!("{} should be a number of MiB, not {:?}", name, mib);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+fn make_depth_view(device: &wgpu::Device, config: &SurfaceConfiguration) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: model::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        })
+        .create_view(&Default::default())
+}
+
+fn max_instances(memory: usize, layout: Layout) -> usize {
+    (memory / layout.stride()).max(1)
+}
+
+fn make_instance_buffer(device: &wgpu::Device, capacity: usize, layout: Layout) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Instance Buffer"),
+        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        size: (capacity * layout.stride()) as wgpu::BufferAddress,
+        mapped_at_creation: false,
+    })
+}
+
+fn digit(keycode: event::VirtualKeyCode) -> Option<char> {
+    use event::VirtualKeyCode::*;
+    let n = match keycode {
+        Key0 | Numpad0 => 0,
+        Key1 | Numpad1 => 1,
+        Key2 | Numpad2 => 2,
+        Key3 | Numpad3 => 3,
+        Key4 | Numpad4 => 4,
+        Key5 | Numpad5 => 5,
+        Key6 | Numpad6 => 6,
+        Key7 | Numpad7 => 7,
+        Key8 | Numpad8 => 8,
+        Key9 | Numpad9 => 9,
+        _ => return None,
+    };
+    std::char::from_digit(n, 10)
+}
+
+impl<A: Automaton> RenderState<A> {
+    // advances playback by one generation in whichever direction the
+    // timeline says
+    fn tick(&mut self) {
+        let next = self.timeline.next(self.game.generation());
+        if next.is_some_and(|next| self.goto(next)) {
+            return;
+        }
+
+        let oldest = self.history.oldest();
+        match self.timeline.loop_range() {
+            Some((lo, _)) if lo < oldest => {
+                info!(
+                    "the bookmark at generation {} isn't in history any more, stopped looping",
+                    lo
+                );
+                for bookmark in self.timeline.bookmarks.iter_mut() {
+                    if bookmark.is_some_and(|b| b < oldest) {
+                        *bookmark = None;
+                    }
+                }
+                self.timeline.looping = false;
+            }
+            _ => {
+                info!(
+                    "generation {} is as far back as history goes, playing forward",
+                    self.game.generation()
+                );
+                self.timeline.reverse = false;
+            }
+        }
+    }
+
+    // shows `generation` from history, or simulates it if it's the one after
+    // the newest. Anything further goes through the clock's queued steps
+    fn goto(&mut self, generation: u64) -> bool {
+        let newest = self.history.newest();
+        if generation <= newest {
+            return self.history.seek(&mut self.game, generation);
+        }
+        if generation > newest + 1 || !self.history.seek(&mut self.game, newest) {
+            return false;
+        }
+        self.step();
+        true
+    }
+
+    // replays a generation we've already seen, otherwise simulates a new one
+    fn step(&mut self) {
+        if self.history.forward(&mut self.game) {
+            return;
+        }
+        let step_start = std::time::Instant::now();
+        let diff = self.game.update();
+        let step_time = step_start.elapsed();
+        self.history.record(&self.game, &diff);
+        self.recorder.record(&self.game, &diff, step_time);
+    }
+
+    // repacks every cell and starts over with a fresh buffer
+    fn rebuild_instances(&mut self, layout: Layout, hide_interior: bool) {
+        let mut instances = self.instances.lock().unwrap();
+        *instances = InstanceTable::from_world(&self.game, layout, hide_interior);
+        let max = max_instances(self.instance_memory, layout);
+        self.instance_capacity = grow_capacity(2048.min(max), instances.len(), max);
+        self.instance_buffer = make_instance_buffer(&self.device, self.instance_capacity, layout);
+        self.capped = false;
+    }
+
+    // the surface and depth texture have to match the window
+    fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        // minimized
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        self.config.width = size.width;
+        self.config.height = size.height;
+        self.surface.configure(&self.device, &self.config);
+        self.depth = make_depth_view(&self.device, &self.config);
+    }
+
+    fn aspect(&self) -> f32 {
+        let size = self.window.inner_size();
+        size.width as f32 / size.height as f32
+    }
+
+    // chunks in view, split into ones near enough for full detail and
+    // far away ones
+    fn visible_chunks(
+        &self,
+        chunks: impl Iterator<Item = Pos>,
+        frustum: &Frustum,
+        eye: na::Point3<f32>,
+    ) -> (Vec<Pos>, Vec<Pos>) {
+        chunks
+            .filter(|c| {
+                let (min, max) = voxel::chunk_box(*c);
+                frustum.intersects(min, max)
+            })
+            .partition(|c| {
+                let (min, max) = voxel::chunk_box(*c);
+                na::distance(&na::center(&min, &max), &eye) < LOD_DISTANCE
+            })
+    }
+
+    // brings the instance buffer up to date, returns the instance ranges
+    // to draw in full and as points
+    fn upload_instances(
+        &mut self,
+        frustum: &Frustum,
+        eye: na::Point3<f32>,
+    ) -> (Vec<Vec<Range<u32>>>, Vec<Range<u32>>) {
+        let mut instances = self.instances.lock().unwrap();
+        let layout = instances.layout();
+        let wanted = instances.len();
+        let max = max_instances(self.instance_memory, layout);
+        let capacity = grow_capacity(self.instance_capacity, wanted, max);
+        if capacity != self.instance_capacity {
+            info!(
+                "growing the instance buffer to {} instances ({} KiB)",
+                capacity,
+                capacity * layout.stride() / 1024
+            );
+            self.instance_buffer = make_instance_buffer(&self.device, capacity, layout);
+            self.instance_capacity = capacity;
+            instances.mark_all_dirty();
+        }
+        if wanted > capacity && !self.capped {
+            warn!(
+                "{} instances don't fit in GOL_INSTANCE_MEMORY, only drawing {}",
+                wanted, capacity
+            );
+        }
+        self.capped = wanted > capacity;
+
+        for run in instances.take_dirty() {
+            // whatever doesn't fit gets uploaded when the buffer grows
+            if run.start >= capacity {
+                continue;
+            }
+            let bytes = run.start * layout.stride()..run.end.min(capacity) * layout.stride();
+            self.queue.write_buffer(
+                &self.instance_buffer,
+                bytes.start as wgpu::BufferAddress,
+                &instances.data()[bytes],
+            );
+        }
+
+        let (near, far) = self.visible_chunks(instances.chunks(), frustum, eye);
+        let fits = |ranges: Vec<Range<u32>>| -> Vec<Range<u32>> {
+            ranges
+                .into_iter()
+                .filter(|r| (r.start as usize) < capacity)
+                .map(|r| r.start..r.end.min(capacity as u32))
+                .collect()
+        };
+        let near = instances
+            .ranges_by_mesh(near, self.meshes.len())
+            .into_iter()
+            .map(fits)
+            .collect();
+        (near, fits(instances.ranges(far, ..)))
+    }
+
+    // remeshes dirty chunks and replaces their buffers
+    fn upload_chunks(&mut self) {
+        let mut chunks = self.chunks.lock().unwrap();
+        if !chunks.is_dirty() {
+            return;
+        }
+        for chunk in chunks.remesh(&self.game) {
+            let (full, far) = match (chunks.get(chunk), chunks.get_box(chunk)) {
+                (Some(full), Some(far)) => (full, far),
+                _ => {
+                    self.chunk_buffers.remove(&chunk);
+                    continue;
+                }
+            };
+            let upload = |mesh: &ChunkMesh| MeshBuffers {
+                vertices: self.device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("Chunk VBO"),
+                    contents: bytemuck::cast_slice(&mesh.vertices),
+                    usage: BufferUsages::VERTEX,
+                }),
+                indices: self.device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("Chunk VBI"),
+                    contents: bytemuck::cast_slice(&mesh.indices),
+                    usage: BufferUsages::INDEX,
+                }),
+                count: mesh.indices.len() as u32,
+            };
+            let buffers = ChunkBuffers {
+                full: upload(full),
+                far: upload(far),
+            };
+            self.chunk_buffers.insert(chunk, buffers);
+        }
+    }
+
+    fn render(&mut self) {
+        let transform = self.camera.get_transform(self.aspect(), self.time);
+        let frustum = Frustum::from_matrix(&transform);
+        let eye = self.camera.eye(self.time);
+
+        let layout = self.instances.lock().unwrap().layout();
+        if !self
+            .pipelines
+            .as_ref()
+            .is_some_and(|p| p.fits(self, layout))
+        {
+            self.pipelines = Some(Pipelines::new(self, layout));
+        }
+        let (near, far) = match self.renderer {
+            Renderer::Instances => self.upload_instances(&frustum, eye),
+            Renderer::Voxels => {
+                self.upload_chunks();
+                (vec![], vec![])
+            }
+        };
+        let pipelines = self.pipelines.as_ref().unwrap();
+        let output = self.surface.get_current_texture().unwrap();
+        let out_view = output.texture.create_view(&Default::default());
+        let mut enc = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+
+        self.queue
+            .write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[transform]));
+        let light = Light {
+            eye: eye.to_homogeneous().into(),
+            ..self.light
+        };
+        self.queue
+            .write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[light]));
+
+        {
+            let mut pass = enc.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &out_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: wgpu::LoadOp::Clear(Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth,
+                    depth_ops: Some(Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            for i in 0..self.bind_groups.len() {
+                pass.set_bind_group(i as u32, &self.bind_groups[i], &[]);
+            }
+
+            match self.renderer {
+                Renderer::Instances => {
+                    pass.set_pipeline(&pipelines.cells);
+                    pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+                    for (mesh, ranges) in self.meshes.iter().zip(near) {
+                        pass.set_bind_group(1, &mesh.material, &[]);
+                        let buffers = &mesh.buffers;
+                        pass.set_index_buffer(buffers.indices.slice(..), wgpu::IndexFormat::Uint32);
+                        pass.set_vertex_buffer(0, buffers.vertices.slice(..));
+                        for range in ranges {
+                            pass.draw_indexed(0..buffers.count, 0, range);
+                        }
+                    }
+
+                    if !far.is_empty() {
+                        pass.set_pipeline(&pipelines.points);
+                        pass.set_vertex_buffer(0, self.point_vbo.slice(..));
+                        for range in far {
+                            pass.draw(0..1, range);
+                        }
+                    }
+                }
+                Renderer::Voxels => {
+                    pass.set_pipeline(&pipelines.voxels);
+                    // untextured, but the layout still has a material
+                    pass.set_bind_group(1, &self.meshes[0].material, &[]);
+                    let (near, far) =
+                        self.visible_chunks(self.chunk_buffers.keys().copied(), &frustum, eye);
+                    let meshes = near
+                        .iter()
+                        .map(|c| &self.chunk_buffers[c].full)
+                        .chain(far.iter().map(|c| &self.chunk_buffers[c].far));
+                    for mesh in meshes {
+                        pass.set_index_buffer(mesh.indices.slice(..), wgpu::IndexFormat::Uint32);
+                        pass.set_vertex_buffer(0, mesh.vertices.slice(..));
+                        pass.draw_indexed(0..mesh.count, 0, 0..1);
+                    }
+                }
+            }
+        }
+
+        self.queue.submit(std::iter::once(enc.finish()));
+        output.present();
+    }
+}
@@ -0,0 +1,348 @@
+//! Cell meshes from glTF files.
+
+use log::warn;
+
+use super::{Material, Model, Texture, Vertex};
+
+/// Loads every triangle of a glTF file's default scene (or of all its
+/// meshes if it has no scenes) into one model, with node transforms applied.
+///
+/// Base color factors are baked into the vertex colors. The texture and
+/// emissive color come from the first primitive's material, the one
+/// material the cell mesh gets.
+pub fn load(filename: &str) -> Result<Model, Box<dyn std::error::Error>> {
+    let (doc, bufs, images) = gltf::import(filename)?;
+    let mut model = Model::default();
+    let mut material = None;
+    match doc.default_scene().or_else(|| doc.scenes().next()) {
+        Some(scene) => {
+            for node in scene.nodes() {
+                add_node(
+                    &mut model,
+                    &mut material,
+                    &node,
+                    na::Matrix4::identity(),
+                    &bufs,
+                )?;
+            }
+        }
+        None => {
+            for mesh in doc.meshes() {
+                add_mesh(
+                    &mut model,
+                    &mut material,
+                    &mesh,
+                    na::Matrix4::identity(),
+                    &bufs,
+                )?;
+            }
+        }
+    }
+
+    if let Some(material) = material {
+        model.material = load_material(&material, &images)?;
+    }
+    Ok(model)
+}
+
+fn add_node<'a>(
+    model: &mut Model,
+    material: &mut Option<gltf::Material<'a>>,
+    node: &gltf::Node<'a>,
+    parent: na::Matrix4<f32>,
+    bufs: &[gltf::buffer::Data],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transform = parent * na::Matrix4::from(node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        add_mesh(model, material, &mesh, transform, bufs)?;
+    }
+    for child in node.children() {
+        add_node(model, material, &child, transform, bufs)?;
+    }
+    Ok(())
+}
+
+fn add_mesh<'a>(
+    model: &mut Model,
+    material: &mut Option<gltf::Material<'a>>,
+    mesh: &gltf::Mesh<'a>,
+    transform: na::Matrix4<f32>,
+    bufs: &[gltf::buffer::Data],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let linear = transform.fixed_slice::<3, 3>(0, 0).into_owned();
+    let normal_matrix = linear
+        .try_inverse()
+        .ok_or("mesh node has a degenerate transform")?
+        .transpose();
+    // mirroring transforms turn counterclockwise triangles clockwise
+    let mirrored = linear.determinant() < 0.0;
+
+    for p in mesh.primitives() {
+        if p.mode() != gltf::mesh::Mode::Triangles {
+            warn!(
+                "skipping a {:?} primitive, only triangles are drawn",
+                p.mode()
+            );
+            continue;
+        }
+        let pbr = p.material().pbr_metallic_roughness();
+        match material {
+            None => *material = Some(p.material()),
+            Some(first) if first.index() != p.material().index() => warn!(
+                "{:?} uses more than one material, only {:?}'s texture and emissive color are drawn",
+                mesh.name(),
+                first.name()
+            ),
+            Some(_) => {}
+        }
+
+        let reader = p.reader(|b| bufs.get(b.index()).map(|data| &data.0[..]));
+        let positions: Vec<[f32; 3]> = reader
+            .read_positions()
+            .ok_or("primitive has no positions")?
+            .collect();
+        let count = positions.len();
+        let normals = reader.read_normals().map(|n| n.collect::<Vec<_>>());
+        let uvs = reader
+            .read_tex_coords(pbr.base_color_texture().map_or(0, |t| t.tex_coord()))
+            .map(|t| t.into_f32().collect::<Vec<_>>());
+        let colors = reader
+            .read_colors(0)
+            .map(|c| c.into_rgba_f32().collect::<Vec<_>>());
+        // the accessors are indexed by vertex, a short one would run out
+        for (name, len) in [
+            ("normals", normals.as_ref().map(Vec::len)),
+            ("texture coordinates", uvs.as_ref().map(Vec::len)),
+            ("colors", colors.as_ref().map(Vec::len)),
+        ] {
+            if let Some(len) = len.filter(|len| *len != count) {
+                return Err(
+                    format!("primitive has {} {} for {} positions", len, name, count).into(),
+                );
+            }
+        }
+        let mut indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..count as u32).collect(),
+        };
+        if indices.iter().any(|i| *i as usize >= count) {
+            return Err("primitive has an index past its last vertex".into());
+        }
+        indices.truncate(indices.len() / 3 * 3);
+        if mirrored {
+            for tri in indices.chunks_exact_mut(3) {
+                tri.swap(1, 2);
+            }
+        }
+
+        let factor = pbr.base_color_factor();
+        let verts = (0..count)
+            .map(|i| Vertex {
+                position: transform.transform_point(&positions[i].into()).into(),
+                normal: normals
+                    .as_ref()
+                    .map(|n| (normal_matrix * na::Vector3::from(n[i])).normalize().into())
+                    .unwrap_or_default(),
+                uv: uvs.as_ref().map(|t| t[i]).unwrap_or_default(),
+                color: {
+                    let color = colors.as_ref().map(|c| c[i]).unwrap_or([1.0; 4]);
+                    [0, 1, 2, 3].map(|j| color[j] * factor[j])
+                },
+            })
+            .collect();
+        let primitive = Model {
+            verts,
+            indices,
+            material: Material::default(),
+        };
+        model.append(if normals.is_some() {
+            primitive
+        } else {
+            primitive.flat()
+        });
+    }
+    Ok(())
+}
+
+fn load_material(
+    material: &gltf::Material,
+    images: &[gltf::image::Data],
+) -> Result<Material, Box<dyn std::error::Error>> {
+    let base_color = match material.pbr_metallic_roughness().base_color_texture() {
+        Some(info) => {
+            let texture = info.texture();
+            let data = images
+                .get(texture.source().index())
+                .ok_or("texture refers to a missing image")?;
+            let sampler = texture.sampler();
+            let address_mode = [sampler.wrap_s(), sampler.wrap_t()].map(|w| match w {
+                gltf::texture::WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+                gltf::texture::WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+                gltf::texture::WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+            });
+            let mag_filter = match sampler.mag_filter() {
+                Some(gltf::texture::MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+                _ => wgpu::FilterMode::Linear,
+            };
+            // there are no mipmaps, so only the filter within a level matters
+            let min_filter = match sampler.min_filter() {
+                Some(
+                    gltf::texture::MinFilter::Nearest
+                    | gltf::texture::MinFilter::NearestMipmapNearest
+                    | gltf::texture::MinFilter::NearestMipmapLinear,
+                ) => wgpu::FilterMode::Nearest,
+                _ => wgpu::FilterMode::Linear,
+            };
+
+            Some(Texture {
+                image: to_rgba(data)?,
+                address_mode,
+                mag_filter,
+                min_filter,
+            })
+        }
+        None => None,
+    };
+
+    Ok(Material {
+        emissive: material.emissive_factor(),
+        base_color,
+    })
+}
+
+// glTF images come decoded in whatever format the file had
+fn to_rgba(data: &gltf::image::Data) -> Result<image::RgbaImage, Box<dyn std::error::Error>> {
+    use gltf::image::Format;
+    use image::{DynamicImage, ImageBuffer};
+
+    let (w, h) = (data.width, data.height);
+    let pixels = data.pixels.clone();
+    let wide = || -> Vec<u16> {
+        data.pixels
+            .chunks_exact(2)
+            .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+            .collect()
+    };
+    let image = match data.format {
+        Format::R8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageLuma8),
+        Format::R8G8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageLumaA8),
+        Format::R8G8B8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageRgb8),
+        Format::R8G8B8A8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageRgba8),
+        Format::B8G8R8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageBgr8),
+        Format::B8G8R8A8 => ImageBuffer::from_raw(w, h, pixels).map(DynamicImage::ImageBgra8),
+        Format::R16 => ImageBuffer::from_raw(w, h, wide()).map(DynamicImage::ImageLuma16),
+        Format::R16G16 => ImageBuffer::from_raw(w, h, wide()).map(DynamicImage::ImageLumaA16),
+        Format::R16G16B16 => ImageBuffer::from_raw(w, h, wide()).map(DynamicImage::ImageRgb16),
+        Format::R16G16B16A16 => ImageBuffer::from_raw(w, h, wide()).map(DynamicImage::ImageRgba16),
+    };
+
+    Ok(image
+        .ok_or("image data is smaller than its size")?
+        .to_rgba8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a one-triangle glTF in the temp directory, its buffer holding
+    // `floats` and then `indices`
+    fn write(name: &str, json: &str, floats: &[f32], indices: &[u16]) -> String {
+        let dir = std::env::temp_dir().join(format!("gol-gltf-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bin: Vec<u8> = floats
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .chain(indices.iter().flat_map(|i| i.to_le_bytes()))
+            .collect();
+        std::fs::write(dir.join(format!("{}.bin", name)), &bin).unwrap();
+        let json = json
+            .replace("NAME", name)
+            .replace("LENGTH", &bin.len().to_string());
+        let path = dir.join(format!("{}.gltf", name));
+        std::fs::write(&path, json).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    const TRIANGLE: [f32; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+
+    fn triangle(node: &str, attributes: &str, accessors: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scene": 0,
+                "scenes": [{{"nodes": [0]}}],
+                "nodes": [{}],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0{}}}}}]}}],
+                "buffers": [{{"uri": "NAME.bin", "byteLength": LENGTH}}],
+                "bufferViews": [{{"buffer": 0, "byteLength": LENGTH}}],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                      "min": [0, 0, 0], "max": [1, 1, 0]}}{}
+                ]
+            }}"#,
+            node, attributes, accessors
+        )
+    }
+
+    #[test]
+    fn loads_the_bundled_box() {
+        let model = load("alexisbox.gltf").unwrap();
+        assert_eq!(model.indices.len(), 36);
+        assert!(model.material.base_color.is_some());
+    }
+
+    #[test]
+    fn applies_node_transforms() {
+        let json = triangle(r#"{"mesh": 0, "translation": [0, 0, 5]}"#, "", "");
+        let model = load(&write("moved", &json, &TRIANGLE, &[])).unwrap();
+        assert!(model.verts.iter().all(|v| v.position[2] == 5.0));
+        // no normals in the file, so they come from the winding
+        assert!(model.verts.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn keeps_mirrored_nodes_facing_out() {
+        let json = triangle(r#"{"mesh": 0, "scale": [-1, 1, 1]}"#, "", "");
+        let model = load(&write("mirrored", &json, &TRIANGLE, &[])).unwrap();
+        assert!(model.verts.iter().any(|v| v.position[0] == -1.0));
+        assert!(model.verts.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn rejects_short_attributes() {
+        // two normals for three positions
+        let json = triangle(
+            r#"{"mesh": 0}"#,
+            r#", "NORMAL": 1"#,
+            r#", {"bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 2, "type": "VEC3"}"#,
+        );
+        let floats = [&TRIANGLE[..], &[0.0, 0.0, 1.0, 0.0, 0.0, 1.0]].concat();
+        let err = load(&write("short", &json, &floats, &[]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("2 normals for 3 positions"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_indices_past_the_last_vertex() {
+        let json = triangle(r#"{"mesh": 0}"#, "", "").replace(
+            r#""attributes": {"POSITION": 0}"#,
+            r#""attributes": {"POSITION": 0}, "indices": 1"#,
+        );
+        let json = json.replace(
+            r#""max": [1, 1, 0]}"#,
+            r#""max": [1, 1, 0]},
+               {"bufferView": 0, "byteOffset": 36, "componentType": 5123, "count": 3, "type": "SCALAR"}"#,
+        );
+        let err = load(&write("indices", &json, &TRIANGLE, &[0, 1, 7]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("index"), "{}", err);
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        assert!(load("no-such-model.gltf").is_err());
+    }
+}
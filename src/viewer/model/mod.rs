@@ -0,0 +1,565 @@
+use std::path::Path;
+
+use game_of_life_3d::game::automaton::Automaton;
+use game_of_life_3d::instance::Layout;
+use game_of_life_3d::voxel::ChunkMesh;
+use log::warn;
+use wgpu::util::DeviceExt;
+use wgpu::{
+    ColorTargetState, ColorWrites, FragmentState, MultisampleState, PrimitiveState,
+    RenderPipelineDescriptor, VertexAttribute, VertexBufferLayout,
+};
+
+mod gltf;
+mod obj;
+mod ply;
+pub mod shapes;
+mod stl;
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck_derive::Pod, bytemuck_derive::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+// white, so a model without colors takes the instance's
+impl Default for Vertex {
+    fn default() -> Vertex {
+        Vertex {
+            position: [0.0; 3],
+            normal: [0.0; 3],
+            uv: [0.0; 2],
+            color: [1.0; 4],
+        }
+    }
+}
+
+fn vertex_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+    const VEC3: wgpu::BufferAddress = std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress;
+    const VEC2: wgpu::BufferAddress = std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress;
+    const ATTRIBUTES: [VertexAttribute; 4] = [
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: 0,
+            shader_location: 0,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: VEC3,
+            shader_location: 1,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x2,
+            offset: 2 * VEC3,
+            shader_location: 2,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x4,
+            offset: 2 * VEC3 + VEC2,
+            shader_location: 3,
+        },
+    ];
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &ATTRIBUTES,
+    }
+}
+
+/// Triangles for the cell mesh, counterclockwise seen from outside.
+#[derive(Debug, Clone, Default)]
+pub struct Model {
+    pub verts: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub material: Material,
+}
+
+/// How the cell mesh's surface looks on top of the vertex colors.
+#[derive(Debug, Clone, Default)]
+pub struct Material {
+    /// Added to the lit color.
+    pub emissive: [f32; 3],
+    /// Multiplies the vertex colors.
+    pub base_color: Option<Texture>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Texture {
+    /// sRGB, like glTF base color textures.
+    pub image: image::RgbaImage,
+    pub address_mode: [wgpu::AddressMode; 2],
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+}
+
+// the shader's material uniform
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck_derive::Pod, bytemuck_derive::Zeroable)]
+struct MaterialUniform {
+    emissive: [f32; 4],
+}
+
+impl Model {
+    /// Adds `other`'s triangles to this model, keeping this one's material.
+    pub fn append(&mut self, other: Model) {
+        let offset = self.verts.len() as u32;
+        self.verts.extend(other.verts);
+        self.indices
+            .extend(other.indices.iter().map(|i| i + offset));
+    }
+
+    /// Every triangle gets its own three vertices with the face normal, for
+    /// models that come without normals.
+    pub fn flat(&self) -> Model {
+        let mut verts = Vec::with_capacity(self.indices.len());
+        for tri in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| self.verts[tri[i] as usize]);
+            let normal = face_normal(a.position, b.position, c.position);
+            for v in [a, b, c] {
+                verts.push(Vertex { normal, ..v });
+            }
+        }
+        let indices = (0..verts.len() as u32).collect();
+
+        Model {
+            verts,
+            indices,
+            material: self.material.clone(),
+        }
+    }
+}
+
+// counterclockwise faces point towards the viewer
+fn face_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let [a, b, c] = [a, b, c].map(na::Point3::from);
+    (b - a)
+        .cross(&(c - a))
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(na::Vector3::y)
+        .into()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Gltf,
+    Obj,
+    Stl,
+    Ply,
+}
+
+// for files without a known extension
+fn sniff(bytes: &[u8]) -> Option<Format> {
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+    let first_line = text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .unwrap_or("");
+    let first_word = first_line.split_whitespace().next().unwrap_or("");
+
+    if bytes.starts_with(b"glTF") || first_line.starts_with('{') {
+        Some(Format::Gltf)
+    } else if first_line == "ply" {
+        Some(Format::Ply)
+    } else if stl::is_binary(bytes) || first_word == "solid" {
+        Some(Format::Stl)
+    } else if matches!(
+        first_word,
+        "v" | "vt" | "vn" | "f" | "o" | "g" | "s" | "mtllib" | "usemtl"
+    ) {
+        Some(Format::Obj)
+    } else {
+        None
+    }
+}
+
+/// A generated shape if there's one called `name` (see [`shapes::NAMES`]),
+/// otherwise the model file at that path.
+pub fn get(name: &str) -> Result<Model, Box<dyn std::error::Error>> {
+    if !Path::new(name).exists() {
+        if let Some(shape) = shapes::get(name) {
+            return Ok(shape);
+        }
+    }
+    load(name)
+}
+
+/// Loads a cell mesh from a glTF, OBJ, STL or PLY file, going by the
+/// extension or, for other names, the file's contents.
+pub fn load(filename: &str) -> Result<Model, Box<dyn std::error::Error>> {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let format = match extension.as_deref() {
+        Some("gltf" | "glb") => Format::Gltf,
+        Some("obj") => Format::Obj,
+        Some("stl") => Format::Stl,
+        Some("ply") => Format::Ply,
+        _ => sniff(&std::fs::read(filename).map_err(|e| format!("{}: {}", filename, e))?)
+            .ok_or_else(|| format!("{} isn't a glTF, OBJ, STL or PLY file", filename))?,
+    };
+
+    let model = match format {
+        Format::Gltf => gltf::load(filename),
+        Format::Obj => obj::load(filename),
+        Format::Stl => stl::load(filename),
+        Format::Ply => ply::load(filename),
+    }
+    .map_err(|e| format!("{}: {}", filename, e))?;
+
+    if model.indices.is_empty() {
+        return Err(format!("{} has no triangles", filename).into());
+    }
+    Ok(model)
+}
+
+// what the shader's vs_main (matrix) and vs_compact entry points read
+fn instance_layout<'a>(layout: Layout) -> VertexBufferLayout<'a> {
+    const VEC4: wgpu::BufferAddress = std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress;
+    const MATRIX: [VertexAttribute; 5] = [
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x4,
+            offset: 0,
+            shader_location: 10,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x4,
+            offset: VEC4,
+            shader_location: 11,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x4,
+            offset: 2 * VEC4,
+            shader_location: 12,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x4,
+            offset: 3 * VEC4,
+            shader_location: 13,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x4,
+            offset: 4 * VEC4,
+            shader_location: 14,
+        },
+    ];
+    const COMPACT: [VertexAttribute; 3] = [
+        VertexAttribute {
+            format: wgpu::VertexFormat::Sint32x3,
+            offset: 0,
+            shader_location: 10,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Unorm8x4,
+            offset: 12,
+            shader_location: 11,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32,
+            offset: 16,
+            shader_location: 12,
+        },
+    ];
+
+    VertexBufferLayout {
+        array_stride: layout.stride() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: match layout {
+            Layout::Matrix => &MATRIX,
+            Layout::Compact => &COMPACT,
+        },
+    }
+}
+
+// chunk mesh vertices, see `ChunkMesh::VERTEX_LEN`
+fn voxel_layout<'a>() -> VertexBufferLayout<'a> {
+    const VEC3: wgpu::BufferAddress = std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress;
+    const ATTRIBUTES: [VertexAttribute; 3] = [
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: 0,
+            shader_location: 0,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: VEC3,
+            shader_location: 1,
+        },
+        VertexAttribute {
+            format: wgpu::VertexFormat::Float32x4,
+            offset: 2 * VEC3,
+            shader_location: 2,
+        },
+    ];
+    VertexBufferLayout {
+        array_stride: (ChunkMesh::VERTEX_LEN * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &ATTRIBUTES,
+    }
+}
+
+/// The pipeline for drawing the cell model once per instance.
+pub fn make_pipeline(
+    state: &super::RenderState<impl Automaton>,
+    instances: Layout,
+) -> Result<wgpu::RenderPipeline, Box<dyn std::error::Error>> {
+    let entry_point = match instances {
+        Layout::Matrix => "vs_main",
+        Layout::Compact => "vs_compact",
+    };
+    build_pipeline(
+        state,
+        entry_point,
+        "fs_main",
+        &[vertex_layout(), instance_layout(instances)],
+        wgpu::PrimitiveTopology::TriangleList,
+    )
+}
+
+/// Like [`make_pipeline`] but drawing a point per instance, for far away
+/// chunks. Draw it with a single vertex at the origin.
+pub fn make_point_pipeline(
+    state: &super::RenderState<impl Automaton>,
+    instances: Layout,
+) -> Result<wgpu::RenderPipeline, Box<dyn std::error::Error>> {
+    let entry_point = match instances {
+        Layout::Matrix => "vs_main",
+        Layout::Compact => "vs_compact",
+    };
+    build_pipeline(
+        state,
+        entry_point,
+        "fs_untextured",
+        &[vertex_layout(), instance_layout(instances)],
+        wgpu::PrimitiveTopology::PointList,
+    )
+}
+
+/// The pipeline for drawing greedy meshed chunks.
+pub fn make_voxel_pipeline(
+    state: &super::RenderState<impl Automaton>,
+) -> Result<wgpu::RenderPipeline, Box<dyn std::error::Error>> {
+    build_pipeline(
+        state,
+        "vs_voxel",
+        "fs_untextured",
+        &[voxel_layout()],
+        wgpu::PrimitiveTopology::TriangleList,
+    )
+}
+
+fn build_pipeline(
+    state: &super::RenderState<impl Automaton>,
+    entry_point: &str,
+    fragment: &str,
+    buffers: &[VertexBufferLayout],
+    topology: wgpu::PrimitiveTopology,
+) -> Result<wgpu::RenderPipeline, Box<dyn std::error::Error>> {
+    let device = &state.device;
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: state
+            .bind_group_layouts
+            .iter()
+            .fold(Vec::new(), |mut acc, l| {
+                acc.push(l);
+                acc
+            })
+            .as_slice(),
+        push_constant_ranges: &[],
+    });
+
+    // built in, so the viewer runs from any directory
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: wgpu::ShaderSource::Wgsl(include_str!("../../shader.wgsl").into()),
+    });
+
+    let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Model pipeline"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point,
+            buffers,
+        },
+        primitive: PrimitiveState {
+            topology,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }),
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: fragment,
+            targets: &[ColorTargetState {
+                format: state.config.format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: ColorWrites::ALL,
+            }],
+        }),
+        multisample: MultisampleState {
+            ..Default::default()
+        },
+        multiview: None,
+    });
+
+    Ok(pipeline)
+}
+
+/// The layout of a material's bind group, group 1 in the shader.
+pub fn make_material_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Material"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+/// The bind group for `material`, with a layout from
+/// [`make_material_layout`]. Models without a texture get a single white
+/// texel.
+pub fn make_material_bind_group(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    material: &Material,
+) -> wgpu::BindGroup {
+    let white = Texture {
+        image: image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])),
+        address_mode: [wgpu::AddressMode::Repeat; 2],
+        mag_filter: wgpu::FilterMode::Nearest,
+        min_filter: wgpu::FilterMode::Nearest,
+    };
+    let texture = material.base_color.as_ref().unwrap_or(&white);
+    // shrink textures the device can't hold
+    let max = device.limits().max_texture_dimension_2d;
+    let (w, h) = texture.image.dimensions();
+    let image = if w.max(h) > max {
+        let scale = max as f32 / w.max(h) as f32;
+        let (w, h) = ((w as f32 * scale) as u32, (h as f32 * scale) as u32);
+        warn!(
+            "shrinking a {}x{} texture to {}x{}",
+            texture.image.width(),
+            texture.image.height(),
+            w,
+            h
+        );
+        std::borrow::Cow::Owned(image::imageops::resize(
+            &texture.image,
+            w.max(1),
+            h.max(1),
+            image::imageops::FilterType::Triangle,
+        ))
+    } else {
+        std::borrow::Cow::Borrowed(&texture.image)
+    };
+    let size = wgpu::Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1,
+    };
+    let gpu_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Base color"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &gpu_texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        image.as_raw(),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(4 * size.width),
+            rows_per_image: std::num::NonZeroU32::new(size.height),
+        },
+        size,
+    );
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Base color"),
+        address_mode_u: texture.address_mode[0],
+        address_mode_v: texture.address_mode[1],
+        mag_filter: texture.mag_filter,
+        min_filter: texture.min_filter,
+        ..Default::default()
+    });
+    let [r, g, b] = material.emissive;
+    let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Material"),
+        contents: bytemuck::cast_slice(&[MaterialUniform {
+            emissive: [r, g, b, 0.0],
+        }]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Material"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    &gpu_texture.create_view(&Default::default()),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: uniform.as_entire_binding(),
+            },
+        ],
+    })
+}
@@ -0,0 +1,246 @@
+//! Wavefront OBJ cell meshes, colored by their MTL materials.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use log::warn;
+
+use super::{face_normal, Model, Vertex};
+
+// a face corner: position, texture coordinate and normal indices
+type Corner = (usize, Option<usize>, Option<usize>);
+
+pub fn load(filename: &str) -> Result<Model, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(filename)?;
+    let dir = Path::new(filename)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    parse(&text, dir)
+}
+
+/// Parses OBJ source, looking for MTL libraries in `dir`. Polygons are
+/// split into triangle fans and faces without normals are flat shaded.
+/// Materials only contribute their diffuse color and opacity.
+pub fn parse(text: &str, dir: &Path) -> Result<Model, Box<dyn std::error::Error>> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    // some exporters put a color after the position
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut materials: HashMap<String, [f32; 4]> = HashMap::new();
+    let mut color = [1.0; 4];
+    let mut model = Model::default();
+    // corners already in the model, with the material color they had
+    let mut seen: HashMap<(Corner, [u32; 4]), u32> = HashMap::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let at = |e: String| format!("line {}: {}", n + 1, e);
+        match keyword {
+            "v" => {
+                let v = floats(rest).map_err(at)?;
+                if v.len() < 3 {
+                    return Err(at("vertex needs three coordinates".into()).into());
+                }
+                positions.push([v[0], v[1], v[2]]);
+                colors.push(match v.len() {
+                    6 | 7 => [v[3], v[4], v[5], 1.0],
+                    _ => [1.0; 4],
+                });
+            }
+            "vt" => {
+                let t = floats(rest).map_err(at)?;
+                // OBJ's v goes up, wgpu's goes down
+                uvs.push([
+                    t.first().copied().unwrap_or(0.0),
+                    1.0 - t.get(1).copied().unwrap_or(0.0),
+                ]);
+            }
+            "vn" => {
+                let v = floats(rest).map_err(at)?;
+                if v.len() < 3 {
+                    return Err(at("normal needs three coordinates".into()).into());
+                }
+                normals.push([v[0], v[1], v[2]]);
+            }
+            "f" => {
+                let corners = rest
+                    .split_whitespace()
+                    .map(|c| corner(c, positions.len(), uvs.len(), normals.len()))
+                    .collect::<Result<Vec<Corner>, String>>()
+                    .map_err(at)?;
+                if corners.len() < 3 {
+                    return Err(at("face needs at least three corners".into()).into());
+                }
+                let color_bits = color.map(f32::to_bits);
+                for i in 1..corners.len() - 1 {
+                    let tri = [corners[0], corners[i], corners[i + 1]];
+                    let vertex = |(v, t, _): Corner, normal: [f32; 3]| Vertex {
+                        position: positions[v],
+                        normal,
+                        uv: t.map(|t| uvs[t]).unwrap_or_default(),
+                        color: [0, 1, 2, 3].map(|j| colors[v][j] * color[j]),
+                    };
+                    if tri.iter().any(|c| c.2.is_none()) {
+                        let normal = face_normal(
+                            positions[tri[0].0],
+                            positions[tri[1].0],
+                            positions[tri[2].0],
+                        );
+                        for c in tri {
+                            model.indices.push(model.verts.len() as u32);
+                            model.verts.push(vertex(c, normal));
+                        }
+                        continue;
+                    }
+                    for c in tri {
+                        let index = *seen.entry((c, color_bits)).or_insert_with(|| {
+                            model.verts.push(vertex(c, normals[c.2.unwrap()]));
+                            model.verts.len() as u32 - 1
+                        });
+                        model.indices.push(index);
+                    }
+                }
+            }
+            "mtllib" => {
+                // a missing library only costs the colors
+                let path = dir.join(rest);
+                match std::fs::read_to_string(&path) {
+                    Ok(mtl) => materials.extend(parse_mtl(&mtl)),
+                    Err(e) => warn!("couldn't read {}: {}", path.display(), e),
+                }
+            }
+            "usemtl" => {
+                color = materials.get(rest).copied().unwrap_or_else(|| {
+                    warn!("line {}: no material called {:?}", n + 1, rest);
+                    [1.0; 4]
+                })
+            }
+            // objects, groups, smoothing groups, lines and the rest
+            _ => {}
+        }
+    }
+
+    Ok(model)
+}
+
+fn floats(s: &str) -> Result<Vec<f32>, String> {
+    s.split_whitespace()
+        .map(|w| w.parse().map_err(|_| format!("bad number {:?}", w)))
+        .collect()
+}
+
+// "v", "v/vt", "v//vn" or "v/vt/vn", 1 based or negative (counting back
+// from the last one so far)
+fn corner(s: &str, positions: usize, uvs: usize, normals: usize) -> Result<Corner, String> {
+    let index = |i: &str, len: usize| -> Result<Option<usize>, String> {
+        if i.is_empty() {
+            return Ok(None);
+        }
+        let i: i64 = i.parse().map_err(|_| format!("bad face corner {:?}", s))?;
+        let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+        if resolved < 0 || resolved >= len as i64 {
+            return Err(format!("face corner {:?} refers past what's defined", s));
+        }
+        Ok(Some(resolved as usize))
+    };
+    let mut parts = s.split('/');
+    let v = index(parts.next().unwrap_or(""), positions)?
+        .ok_or_else(|| format!("face corner {:?} has no position", s))?;
+    let vt = index(parts.next().unwrap_or(""), uvs)?;
+    let vn = index(parts.next().unwrap_or(""), normals)?;
+    Ok((v, vt, vn))
+}
+
+// diffuse colors (Kd) with opacity (d, or Tr for transparency) by name
+fn parse_mtl(text: &str) -> HashMap<String, [f32; 4]> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, [f32; 4])> = None;
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let values = floats(rest).unwrap_or_default();
+        match (keyword, current.as_mut()) {
+            ("newmtl", _) => {
+                materials.extend(current.take());
+                current = Some((rest.to_string(), [1.0; 4]));
+            }
+            ("Kd", Some((_, color))) if values.len() >= 3 => {
+                color[..3].copy_from_slice(&values[..3])
+            }
+            ("d", Some((_, color))) if !values.is_empty() => color[3] = values[0],
+            ("Tr", Some((_, color))) if !values.is_empty() => color[3] = 1.0 - values[0],
+            _ => {}
+        }
+    }
+    materials.extend(current);
+    materials
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &str = "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\n";
+
+    #[test]
+    fn fans_polygons_with_flat_normals() {
+        let model = parse(&format!("{}f 1 2 3 4\n", SQUARE), Path::new("")).unwrap();
+        assert_eq!(model.indices.len(), 6);
+        assert!(model.verts.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn shares_corners_with_normals() {
+        let text = format!(
+            "{}vn 0 0 1\nf 1//1 2//1 3//1\nf -4//1 -2//1 -1//1\n",
+            SQUARE
+        );
+        let model = parse(&text, Path::new("")).unwrap();
+        assert_eq!(model.indices.len(), 6);
+        // corners 1 and 3 are in both triangles
+        assert_eq!(model.verts.len(), 4);
+    }
+
+    #[test]
+    fn reads_texture_coordinates_and_vertex_colors() {
+        let text = "v 0 0 0 1 0 0\nv 1 0 0 1 0 0\nv 0 1 0 1 0 0\nvt 0 0\nvt 1 1\nf 1/1 2/2 3/1\n";
+        let model = parse(text, Path::new("")).unwrap();
+        assert_eq!(model.verts[1].uv, [1.0, 0.0]);
+        assert!(model.verts.iter().all(|v| v.color == [1.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn colors_faces_by_material() {
+        let dir = std::env::temp_dir().join(format!("gol-obj-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("red.mtl"), "newmtl red\nKd 1 0 0\nd 0.5\n").unwrap();
+        let text = format!("mtllib red.mtl\n{}usemtl red\nf 1 2 3\n", SQUARE);
+        let model = parse(&text, &dir).unwrap();
+        assert!(model.verts.iter().all(|v| v.color == [1.0, 0.0, 0.0, 0.5]));
+    }
+
+    #[test]
+    fn survives_a_missing_material_library() {
+        let text = format!("mtllib nowhere.mtl\n{}usemtl red\nf 1 2 3\n", SQUARE);
+        let model = parse(&text, Path::new("/nonexistent")).unwrap();
+        assert_eq!(model.indices.len(), 3);
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        for (text, line) in [
+            (format!("{}f 1 2 9\n", SQUARE), "line 5"),
+            (format!("{}f 1 2\n", SQUARE), "line 5"),
+            ("v 1 2\n".to_string(), "line 1"),
+            (format!("{}f 1/1 2 3\n", SQUARE), "line 5"),
+            ("v 1 x 2\n".to_string(), "line 1"),
+        ] {
+            let err = parse(&text, Path::new("")).unwrap_err().to_string();
+            assert!(err.starts_with(line), "{:?}: {}", text, err);
+        }
+    }
+}
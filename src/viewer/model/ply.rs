@@ -0,0 +1,353 @@
+//! PLY (Stanford polygon) cell meshes, ASCII or binary.
+
+use super::{Model, Vertex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // colors stored as integers go from 0 to the type's max
+    fn max(self) -> f64 {
+        match self {
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            Scalar::I8 => i8::MAX as f64,
+            Scalar::I16 => i16::MAX as f64,
+            Scalar::I32 => i32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    /// Name, count type and item type.
+    List(String, Scalar, Scalar),
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// the body after the header, read one value at a time
+enum Values<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl Values<'_> {
+    fn next(&mut self, ty: Scalar) -> Result<f64, Box<dyn std::error::Error>> {
+        match self {
+            Values::Ascii(words) => {
+                let w = words.next().ok_or("file ends before its elements do")?;
+                Ok(w.parse().map_err(|_| format!("bad number {:?}", w))?)
+            }
+            Values::Binary { bytes, big_endian } => {
+                if bytes.len() < ty.size() {
+                    return Err("file ends before its elements do".into());
+                }
+                let (value, rest) = bytes.split_at(ty.size());
+                *bytes = rest;
+                let mut raw = [0; 8];
+                raw[..value.len()].copy_from_slice(value);
+                if *big_endian {
+                    raw[..value.len()].reverse();
+                }
+                Ok(match ty {
+                    Scalar::I8 => raw[0] as i8 as f64,
+                    Scalar::U8 => raw[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(raw),
+                })
+            }
+        }
+    }
+}
+
+pub fn load(filename: &str) -> Result<Model, Box<dyn std::error::Error>> {
+    parse(&std::fs::read(filename)?)
+}
+
+/// Parses a PLY file with a "vertex" and a "face" element. Vertices may
+/// have normals, colors and texture coordinates, faces without normals get
+/// flat shaded and polygons are split into triangle fans. Other elements
+/// are skipped.
+pub fn parse(bytes: &[u8]) -> Result<Model, Box<dyn std::error::Error>> {
+    // the header ends at a line of its own, "end_header" in a comment
+    // doesn't count
+    let mut end = 0;
+    let body_start = loop {
+        let newline = bytes[end..].iter().position(|b| *b == b'\n');
+        let line_end = newline.map_or(bytes.len(), |i| end + i);
+        if bytes[end..line_end].trim_ascii() == b"end_header" {
+            break (line_end + 1).min(bytes.len());
+        }
+        if newline.is_none() {
+            return Err("PLY header has no end_header".into());
+        }
+        end = line_end + 1;
+    };
+    let header = std::str::from_utf8(&bytes[..end]).map_err(|_| "PLY header isn't text")?;
+    let body = &bytes[body_start..];
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err("PLY files start with \"ply\"".into());
+    }
+    let mut values = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let bad = || format!("bad PLY header line {:?}", line);
+        match words.as_slice() {
+            ["format", "ascii", _] => {
+                let text = std::str::from_utf8(body).map_err(|_| "ASCII PLY body isn't text")?;
+                values = Some(Values::Ascii(text.split_ascii_whitespace()));
+            }
+            ["format", "binary_little_endian", _] => {
+                values = Some(Values::Binary {
+                    bytes: body,
+                    big_endian: false,
+                })
+            }
+            ["format", "binary_big_endian", _] => {
+                values = Some(Values::Binary {
+                    bytes: body,
+                    big_endian: true,
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| bad())?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .ok_or_else(bad)?
+                .properties
+                .push(Property::List(
+                    name.to_string(),
+                    Scalar::parse(count).ok_or_else(bad)?,
+                    Scalar::parse(item).ok_or_else(bad)?,
+                )),
+            ["property", ty, name] => {
+                elements
+                    .last_mut()
+                    .ok_or_else(bad)?
+                    .properties
+                    .push(Property::Scalar(
+                        name.to_string(),
+                        Scalar::parse(ty).ok_or_else(bad)?,
+                    ))
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(bad().into()),
+        }
+    }
+    let mut values = values.ok_or("PLY header has no format")?;
+    // reading an element is what uses up the body, one without properties
+    // could claim any count without the file ending
+    if let Some(empty) = elements
+        .iter()
+        .find(|e| e.count > 0 && e.properties.is_empty())
+    {
+        return Err(format!("PLY element {:?} has no properties", empty.name).into());
+    }
+
+    let mut model = Model::default();
+    let mut has_normals = false;
+    for element in elements.iter() {
+        for _ in 0..element.count {
+            let mut vertex = Vertex::default();
+            for property in element.properties.iter() {
+                match property {
+                    Property::Scalar(name, ty) => {
+                        let value = values.next(*ty)?;
+                        if element.name != "vertex" {
+                            continue;
+                        }
+                        let color = (value / ty.max()) as f32;
+                        match name.as_str() {
+                            "x" => vertex.position[0] = value as f32,
+                            "y" => vertex.position[1] = value as f32,
+                            "z" => vertex.position[2] = value as f32,
+                            "nx" => vertex.normal[0] = value as f32,
+                            "ny" => vertex.normal[1] = value as f32,
+                            "nz" => vertex.normal[2] = value as f32,
+                            "red" | "r" => vertex.color[0] = color,
+                            "green" | "g" => vertex.color[1] = color,
+                            "blue" | "b" => vertex.color[2] = color,
+                            "alpha" | "a" => vertex.color[3] = color,
+                            "s" | "u" | "texture_u" | "texture_s" => vertex.uv[0] = value as f32,
+                            // PLY's v goes up, wgpu's goes down
+                            "t" | "v" | "texture_v" | "texture_t" => {
+                                vertex.uv[1] = 1.0 - value as f32
+                            }
+                            _ => {}
+                        }
+                        has_normals |= name == "nx";
+                    }
+                    Property::List(name, count, item) => {
+                        let count = values.next(*count)? as usize;
+                        // the count is straight from the file, a corrupt one
+                        // runs out of input long before it's reached
+                        let mut polygon = Vec::new();
+                        for _ in 0..count {
+                            polygon.push(values.next(*item)? as u32);
+                        }
+                        let is_face = element.name == "face"
+                            && matches!(name.as_str(), "vertex_indices" | "vertex_index");
+                        if is_face && polygon.len() >= 3 {
+                            for i in 1..polygon.len() - 1 {
+                                model.indices.extend_from_slice(&[
+                                    polygon[0],
+                                    polygon[i],
+                                    polygon[i + 1],
+                                ]);
+                            }
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                model.verts.push(vertex);
+            }
+        }
+    }
+
+    if model
+        .indices
+        .iter()
+        .any(|i| *i as usize >= model.verts.len())
+    {
+        return Err("face refers to a missing vertex".into());
+    }
+    Ok(if has_normals { model } else { model.flat() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "ply\nformat ascii 1.0\ncomment a square\n\
+        element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+        -1 -1 0 255 0 0\n1 -1 0 255 0 0\n1 1 0 255 0 0\n-1 1 0 255 0 0\n4 0 1 2 3\n";
+
+    fn binary(big_endian: bool, face_count: u32, faces: &[u32]) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut bytes = format!(
+            "ply\nformat {} 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+             property float z\nelement face 1\nproperty list uint uint vertex_indices\n\
+             end_header\n",
+            format
+        )
+        .into_bytes();
+        let floats = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let ints = std::iter::once(face_count).chain(faces.iter().copied());
+        if big_endian {
+            bytes.extend(floats.iter().flat_map(|f| f.to_be_bytes()));
+            bytes.extend(ints.flat_map(|i| i.to_be_bytes()));
+        } else {
+            bytes.extend(floats.iter().flat_map(|f| f.to_le_bytes()));
+            bytes.extend(ints.flat_map(|i| i.to_le_bytes()));
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_ascii_with_colors() {
+        let model = parse(ASCII.as_bytes()).unwrap();
+        assert_eq!(model.indices.len(), 6);
+        assert!(model.verts.iter().all(|v| v.color == [1.0, 0.0, 0.0, 1.0]));
+        assert!(model.verts.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn ends_the_header_only_at_its_own_line() {
+        let text = ASCII.replace("comment a square", "comment see end_header below");
+        assert_eq!(parse(text.as_bytes()).unwrap().indices.len(), 6);
+        let text = ASCII.replace("end_header\n", "end_header\r\n");
+        assert_eq!(parse(text.as_bytes()).unwrap().indices.len(), 6);
+    }
+
+    #[test]
+    fn reads_binary_either_endianness() {
+        for big_endian in [false, true] {
+            let model = parse(&binary(big_endian, 3, &[0, 1, 2])).unwrap();
+            assert_eq!(model.indices.len(), 3);
+            assert_eq!(model.verts[1].position, [1.0, 0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        // a face claiming four billion corners runs out of input
+        assert!(parse(&binary(false, 4_000_000_000, &[0, 1, 2])).is_err());
+        // and so would four billion vertices, if they had anything to read
+        let empty = ASCII.replace("element face 1", "element empty 4000000000\nelement face 1");
+        assert!(parse(empty.as_bytes()).is_err());
+        // a corner that isn't there
+        assert!(parse(&binary(false, 3, &[0, 1, 9])).is_err());
+        // cut short
+        let mut bytes = binary(false, 3, &[0, 1, 2]);
+        bytes.truncate(bytes.len() - 2);
+        assert!(parse(&bytes).is_err());
+
+        for text in [
+            ASCII.replace("end_header", "end_of_header"),
+            ASCII.replace("ply\n", "yelp\n"),
+            ASCII.replace("format ascii 1.0\n", ""),
+            ASCII.replace("property uchar red", "property colour red"),
+            ASCII.replace("4 0 1 2 3", "4 0 1 2 three"),
+        ] {
+            assert!(parse(text.as_bytes()).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! Cell meshes generated in code, for when there's no model file. All of
+//! them fit in the same -1 to 1 cube the model files are made for.
+
+use std::collections::HashMap;
+
+use super::{face_normal, Model, Vertex};
+
+/// Every shape [`get`] knows.
+pub const NAMES: &[&str] = &[
+    "cube",
+    "bevelled-cube",
+    "uv-sphere",
+    "icosphere",
+    "octahedron",
+    "tetrahedron",
+    "quad",
+];
+
+// "Bevelled Cube" and "bevelled-cube" are the same thing
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '-' | '_' | ' '))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The shape called `name`, ignoring case and separators.
+pub fn get(name: &str) -> Option<Model> {
+    Some(match normalize(name).as_str() {
+        "cube" => cube(),
+        "bevelledcube" | "beveledcube" => bevelled_cube(0.2),
+        "uvsphere" | "sphere" => uv_sphere(24, 12),
+        "icosphere" => icosphere(2),
+        "octahedron" => octahedron(),
+        "tetrahedron" => tetrahedron(),
+        "quad" => quad(),
+        _ => return None,
+    })
+}
+
+// flat shaded convex polygons, each with its corners in order around it
+// either way, turned to face away from the middle
+fn convex(faces: &[Vec<[f32; 3]>]) -> Model {
+    let mut model = Model::default();
+    for face in faces {
+        for i in 1..face.len() - 1 {
+            let mut tri = [face[0], face[i], face[i + 1]];
+            let normal = na::Vector3::from(face_normal(tri[0], tri[1], tri[2]));
+            if normal.dot(&na::Vector3::from(tri[0])) < 0.0 {
+                tri.swap(1, 2);
+            }
+            for position in tri {
+                model.indices.push(model.verts.len() as u32);
+                model.verts.push(Vertex {
+                    position,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    model.flat()
+}
+
+// a point with `along` on axis `d` and `u`, `v` on the next two
+fn on_axis(d: usize, along: f32, u: f32, v: f32) -> [f32; 3] {
+    let mut p = [0.0; 3];
+    p[d] = along;
+    p[(d + 1) % 3] = u;
+    p[(d + 2) % 3] = v;
+    p
+}
+
+pub fn cube() -> Model {
+    bevelled_cube(0.0)
+}
+
+/// A cube with its edges and corners cut off `bevel` in from each face.
+/// With no bevel it's a plain cube.
+pub fn bevelled_cube(bevel: f32) -> Model {
+    let inner = 1.0 - bevel;
+    let mut faces = Vec::new();
+    for d in 0..3 {
+        for s in [1.0, -1.0] {
+            faces.push(vec![
+                on_axis(d, s, inner, inner),
+                on_axis(d, s, -inner, inner),
+                on_axis(d, s, -inner, -inner),
+                on_axis(d, s, inner, -inner),
+            ]);
+        }
+    }
+    if bevel > 0.0 {
+        // between faces d and d + 1
+        for d in 0..3 {
+            for su in [1.0, -1.0] {
+                for sv in [1.0, -1.0] {
+                    faces.push(vec![
+                        on_axis(d, su, sv * inner, inner),
+                        on_axis(d, su * inner, sv, inner),
+                        on_axis(d, su * inner, sv, -inner),
+                        on_axis(d, su, sv * inner, -inner),
+                    ]);
+                }
+            }
+        }
+        for x in [1.0, -1.0] {
+            for y in [1.0, -1.0] {
+                for z in [1.0, -1.0] {
+                    faces.push(vec![
+                        [x, y * inner, z * inner],
+                        [x * inner, y, z * inner],
+                        [x * inner, y * inner, z],
+                    ]);
+                }
+            }
+        }
+    }
+    convex(&faces)
+}
+
+pub fn octahedron() -> Model {
+    let mut faces = Vec::new();
+    for x in [1.0, -1.0] {
+        for y in [1.0, -1.0] {
+            for z in [1.0, -1.0] {
+                faces.push(vec![[x, 0.0, 0.0], [0.0, y, 0.0], [0.0, 0.0, z]]);
+            }
+        }
+    }
+    convex(&faces)
+}
+
+/// Every other corner of the cube.
+pub fn tetrahedron() -> Model {
+    let corners = [
+        [1.0, 1.0, 1.0],
+        [1.0, -1.0, -1.0],
+        [-1.0, 1.0, -1.0],
+        [-1.0, -1.0, 1.0],
+    ];
+    let faces: Vec<Vec<[f32; 3]>> = (0..4)
+        .map(|skip| (0..4).filter(|&i| i != skip).map(|i| corners[i]).collect())
+        .collect();
+    convex(&faces)
+}
+
+/// A smooth sphere of `segments` around and `rings` from pole to pole,
+/// with texture coordinates wrapping around it once.
+pub fn uv_sphere(segments: u32, rings: u32) -> Model {
+    let mut model = Model::default();
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let polar = v * std::f32::consts::PI;
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let azimuth = u * std::f32::consts::TAU;
+            let p = [
+                polar.sin() * azimuth.cos(),
+                polar.cos(),
+                -polar.sin() * azimuth.sin(),
+            ];
+            model.verts.push(Vertex {
+                position: p,
+                normal: p,
+                uv: [u, v],
+                ..Default::default()
+            });
+        }
+    }
+    let at = |ring: u32, segment: u32| ring * (segments + 1) + segment;
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let (a, b) = (at(ring, segment), at(ring, segment + 1));
+            let (c, d) = (at(ring + 1, segment), at(ring + 1, segment + 1));
+            // the triangles touching the poles would have no area
+            if ring != 0 {
+                model.indices.extend_from_slice(&[a, c, b]);
+            }
+            if ring != rings - 1 {
+                model.indices.extend_from_slice(&[b, c, d]);
+            }
+        }
+    }
+    model
+}
+
+/// An icosahedron with each triangle split in four `subdivisions` times,
+/// pushed out onto a smooth sphere.
+pub fn icosphere(subdivisions: u32) -> Model {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut points: Vec<na::Vector3<f32>> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .iter()
+    .map(|p| na::Vector3::from(*p).normalize())
+    .collect();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // edges shared by two triangles get one midpoint
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push((points[a as usize] + points[b as usize]).normalize());
+                points.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    Model {
+        verts: points
+            .iter()
+            .map(|p| Vertex {
+                position: (*p).into(),
+                normal: (*p).into(),
+                ..Default::default()
+            })
+            .collect(),
+        indices: triangles.concat(),
+        ..Default::default()
+    }
+}
+
+/// A square in the z = 0 plane facing both ways, for 2D boards.
+pub fn quad() -> Model {
+    let corners = [
+        [1.0, 1.0, 0.0],
+        [-1.0, 1.0, 0.0],
+        [-1.0, -1.0, 0.0],
+        [1.0, -1.0, 0.0],
+    ];
+    let mut model = Model::default();
+    for (normal, indices) in [
+        ([0.0, 0.0, 1.0], [0, 1, 2, 0, 2, 3]),
+        ([0.0, 0.0, -1.0], [0, 2, 1, 0, 3, 2]),
+    ] {
+        let first = model.verts.len() as u32;
+        for position in corners {
+            model.verts.push(Vertex {
+                position,
+                normal,
+                uv: [(position[0] + 1.0) / 2.0, (1.0 - position[1]) / 2.0],
+                ..Default::default()
+            });
+        }
+        model.indices.extend(indices.iter().map(|i| first + i));
+    }
+    model
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(model: &Model, i: u32) -> na::Point3<f32> {
+        model.verts[i as usize].position.into()
+    }
+
+    // each triangle's winding normal, and its middle
+    fn triangles(model: &Model) -> Vec<(na::Vector3<f32>, na::Point3<f32>)> {
+        model
+            .indices
+            .chunks(3)
+            .map(|t| {
+                let (a, b, c) = (point(model, t[0]), point(model, t[1]), point(model, t[2]));
+                ((b - a).cross(&(c - a)), na::center(&na::center(&a, &b), &c))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn solids_wind_outward() {
+        for name in NAMES.iter().filter(|n| **n != "quad") {
+            let model = get(name).unwrap();
+            assert!(!model.indices.is_empty(), "{}", name);
+            let centroid = model.verts.iter().fold(na::Vector3::zeros(), |sum, v| {
+                sum + na::Vector3::from(v.position)
+            }) / model.verts.len() as f32;
+            for (normal, middle) in triangles(&model) {
+                let outward = middle.coords - centroid;
+                assert!(normal.dot(&outward) > 0.0, "{} at {}", name, middle);
+            }
+        }
+    }
+
+    #[test]
+    fn quad_faces_both_ways() {
+        let normals: Vec<f32> = triangles(&quad())
+            .iter()
+            .map(|(n, _)| n.z.signum())
+            .collect();
+        assert_eq!(normals, [1.0, 1.0, -1.0, -1.0]);
+    }
+
+    #[test]
+    fn finds_shapes_by_loose_name() {
+        for name in NAMES {
+            assert!(get(name).is_some(), "{}", name);
+        }
+        assert!(get("Bevelled Cube").is_some());
+        assert!(get("UV_Sphere").is_some());
+        assert!(get("dodecahedron").is_none());
+    }
+}
@@ -0,0 +1,156 @@
+//! STL cell meshes, binary or ASCII.
+
+use super::{Model, Vertex};
+
+pub fn load(filename: &str) -> Result<Model, Box<dyn std::error::Error>> {
+    parse(&std::fs::read(filename)?)
+}
+
+/// Binary STL is an 80 byte header, a triangle count and 50 bytes per
+/// triangle. Some binary files start with "solid" like ASCII ones, so the
+/// size is what tells them apart.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.len() >= 84
+        && 84 + 50 * u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as u64
+            == bytes.len() as u64
+}
+
+/// Parses a binary or ASCII STL file. The facet normals are left out in
+/// favor of ones from the winding, which exporters get right more often.
+pub fn parse(bytes: &[u8]) -> Result<Model, Box<dyn std::error::Error>> {
+    let triangles = if is_binary(bytes) {
+        binary(bytes)
+    } else {
+        ascii(std::str::from_utf8(bytes).map_err(|_| "neither binary nor ASCII STL")?)?
+    };
+
+    let model = Model {
+        verts: triangles
+            .into_iter()
+            .flatten()
+            .map(|position| Vertex {
+                position,
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let indices = (0..model.verts.len() as u32).collect();
+    Ok(Model { indices, ..model }.flat())
+}
+
+fn binary(bytes: &[u8]) -> Vec<[[f32; 3]; 3]> {
+    bytes[84..]
+        .chunks_exact(50)
+        .map(|facet| {
+            // skipping the normal
+            let float = |i: usize| {
+                let at = 12 + i * 4;
+                f32::from_le_bytes([facet[at], facet[at + 1], facet[at + 2], facet[at + 3]])
+            };
+            [0, 1, 2].map(|v| [float(v * 3), float(v * 3 + 1), float(v * 3 + 2)])
+        })
+        .collect()
+}
+
+fn ascii(text: &str) -> Result<Vec<[[f32; 3]; 3]>, Box<dyn std::error::Error>> {
+    let mut words = text.split_whitespace();
+    if words.next() != Some("solid") {
+        return Err("ASCII STL should start with \"solid\"".into());
+    }
+
+    let mut triangles = Vec::new();
+    let mut facet: Vec<[f32; 3]> = Vec::new();
+    while let Some(word) = words.next() {
+        match word {
+            "vertex" => {
+                let mut v = [0.0; 3];
+                for c in v.iter_mut() {
+                    let w = words.next().ok_or("vertex needs three coordinates")?;
+                    *c = w.parse().map_err(|_| format!("bad number {:?}", w))?;
+                }
+                facet.push(v);
+            }
+            // a few writers put polygons in a facet, fan them out
+            "endfacet" => {
+                if facet.len() < 3 {
+                    return Err("facet needs at least three vertices".into());
+                }
+                for i in 1..facet.len() - 1 {
+                    triangles.push([facet[0], facet[i], facet[i + 1]]);
+                }
+                facet.clear();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    fn binary_stl(header: &[u8], triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(80, 0);
+        bytes.extend((triangles.len() as u32).to_le_bytes());
+        for tri in triangles {
+            // a wrong normal, which gets ignored
+            bytes.extend([0.0f32, 0.0, -1.0].iter().flat_map(|f| f.to_le_bytes()));
+            for v in tri {
+                bytes.extend(v.iter().flat_map(|f| f.to_le_bytes()));
+            }
+            bytes.extend([0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_binary() {
+        let model = parse(&binary_stl(b"binary", &[TRIANGLE, TRIANGLE])).unwrap();
+        assert_eq!(model.indices.len(), 6);
+        assert!(model.verts.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn reads_binary_starting_with_solid() {
+        let bytes = binary_stl(b"solid but binary", &[TRIANGLE]);
+        assert!(is_binary(&bytes));
+        assert_eq!(parse(&bytes).unwrap().verts[1].position, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn reads_ascii() {
+        let text = "solid t\nfacet normal 0 0 1\nouter loop\n\
+                    vertex 0 0 0\nvertex 1 0 0\nvertex 1 1 0\nvertex 0 1 0\n\
+                    endloop\nendfacet\nendsolid t\n";
+        assert!(!is_binary(text.as_bytes()));
+        // a quad facet is fanned into two triangles
+        assert_eq!(parse(text.as_bytes()).unwrap().indices.len(), 6);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        for bytes in [
+            &b"solid t\nfacet\nvertex 0 0\nendfacet\n"[..],
+            b"solid t\nfacet\nvertex 0 0 0\nvertex 1 0 0\nendfacet\n",
+            b"solid t\nfacet\nvertex 0 zero 0\n",
+            b"not an stl",
+            &[0xff; 10],
+        ] {
+            assert!(
+                parse(bytes).is_err(),
+                "{:?}",
+                String::from_utf8_lossy(bytes)
+            );
+        }
+        // a truncated binary file doesn't match its triangle count
+        let mut bytes = binary_stl(b"binary", &[TRIANGLE]);
+        bytes.truncate(100);
+        assert!(parse(&bytes).is_err());
+    }
+}
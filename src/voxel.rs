@@ -0,0 +1,406 @@
+//! Chunked, greedy meshed voxel geometry for the board.
+//!
+//! Instead of a mesh per cell, each chunk of the board gets one mesh made of
+//! the faces between live and dead cells, with coplanar faces of the same
+//! color merged into rectangles. Cells fill their whole unit cube and the
+//! appearance's scale is ignored.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::game::automaton::Automaton;
+use crate::game::{bounds, Diff, Pos, World};
+
+/// Cells along each side of a chunk.
+pub const CHUNK: i32 = 16;
+
+/// Which chunk a cell falls in.
+pub fn chunk_of(pos: Pos) -> Pos {
+    (
+        pos.0.div_euclid(CHUNK),
+        pos.1.div_euclid(CHUNK),
+        pos.2.div_euclid(CHUNK),
+    )
+}
+
+/// The space chunk `chunk` covers, cells fill their unit cubes.
+pub fn chunk_box(chunk: Pos) -> (na::Point3<f32>, na::Point3<f32>) {
+    let min = na::Point3::new(chunk.0 as f32, chunk.1 as f32, chunk.2 as f32) * CHUNK as f32;
+    (
+        min - na::Vector3::repeat(0.5),
+        min + na::Vector3::repeat(CHUNK as f32 - 0.5),
+    )
+}
+
+/// Triangles for one chunk. Vertices are [`ChunkMesh::VERTEX_LEN`] floats:
+/// the position in board coordinates, the normal, then the RGBA color.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkMesh {
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
+impl ChunkMesh {
+    pub const VERTEX_LEN: usize = 3 + 3 + 4;
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn quad(&mut self, corners: [[f32; 3]; 4], normal: [f32; 3], color: [f32; 4]) {
+        let first = (self.vertices.len() / ChunkMesh::VERTEX_LEN) as u32;
+        for c in corners {
+            self.vertices.extend_from_slice(&c);
+            self.vertices.extend_from_slice(&normal);
+            self.vertices.extend_from_slice(&color);
+        }
+        self.indices
+            .extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+}
+
+/// Meshes for every chunk with live cells in it. Chunks touched by a diff
+/// are marked dirty and only those get rebuilt.
+///
+/// Each chunk also gets a single box around its cells, for drawing it
+/// from far away.
+#[derive(Debug, Default)]
+pub struct Chunks {
+    meshes: HashMap<Pos, ChunkMesh>,
+    boxes: HashMap<Pos, ChunkMesh>,
+    dirty: HashSet<Pos>,
+}
+
+impl Chunks {
+    /// Every chunk of `world` marked dirty, nothing meshed yet.
+    pub fn from_world<A: Automaton>(world: &World<A>) -> Chunks {
+        let mut chunks = Chunks::default();
+        for (pos, _) in world.cells() {
+            chunks.dirty.insert(chunk_of(pos));
+        }
+        chunks
+    }
+
+    pub fn get(&self, chunk: Pos) -> Option<&ChunkMesh> {
+        self.meshes.get(&chunk)
+    }
+
+    /// The low detail stand in for a chunk.
+    pub fn get_box(&self, chunk: Pos) -> Option<&ChunkMesh> {
+        self.boxes.get(&chunk)
+    }
+
+    /// Marks the chunks a diff touched, meant to be called from a
+    /// [`World::subscribe`] observer.
+    pub fn mark<S>(&mut self, diff: &Diff<S>) {
+        for c in diff.changes.iter() {
+            let p = c.pos;
+            // a cell on the edge of its chunk hides faces in the next one
+            for n in [
+                p,
+                (p.0 - 1, p.1, p.2),
+                (p.0 + 1, p.1, p.2),
+                (p.0, p.1 - 1, p.2),
+                (p.0, p.1 + 1, p.2),
+                (p.0, p.1, p.2 - 1),
+                (p.0, p.1, p.2 + 1),
+            ] {
+                self.dirty.insert(chunk_of(n));
+            }
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Rebuilds the dirty chunks from `world`. Returns the chunks whose
+    /// mesh changed, ones that ended up empty are dropped.
+    pub fn remesh<A: Automaton>(&mut self, world: &World<A>) -> Vec<Pos> {
+        let dirty: Vec<Pos> = self.dirty.drain().collect();
+        for chunk in dirty.iter() {
+            let mesh = mesh_chunk(world, *chunk);
+            if mesh.is_empty() {
+                self.meshes.remove(chunk);
+                self.boxes.remove(chunk);
+            } else {
+                self.meshes.insert(*chunk, mesh);
+                self.boxes.insert(*chunk, box_chunk(world, *chunk));
+            }
+        }
+        dirty
+    }
+}
+
+// the chunk plus a one cell border, indexed by x, then y, then z
+struct Block<S> {
+    cells: Vec<S>,
+}
+
+impl<S: Copy + Default> Block<S> {
+    const SIDE: i32 = CHUNK + 2;
+
+    fn get(&self, p: [i32; 3]) -> S {
+        let i = (p[0] + 1) + (p[1] + 1) * Self::SIDE + (p[2] + 1) * Self::SIDE * Self::SIDE;
+        self.cells[i as usize]
+    }
+}
+
+/// One box around every cell of a chunk, colored like the first one found.
+pub fn box_chunk<A: Automaton>(world: &World<A>, chunk: Pos) -> ChunkMesh {
+    let cells: Vec<(Pos, A::State)> = world
+        .cells_in(
+            (chunk.0 * CHUNK, chunk.1 * CHUNK, chunk.2 * CHUNK),
+            (
+                chunk.0 * CHUNK + CHUNK - 1,
+                chunk.1 * CHUNK + CHUNK - 1,
+                chunk.2 * CHUNK + CHUNK - 1,
+            ),
+        )
+        .collect();
+    let mut mesh = ChunkMesh::default();
+    let Some(((x0, y0, z0), (x1, y1, z1))) = bounds(cells.iter().map(|c| c.0)) else {
+        return mesh;
+    };
+    let lo = [x0 as f32 - 0.5, y0 as f32 - 0.5, z0 as f32 - 0.5];
+    let hi = [x1 as f32 + 0.5, y1 as f32 + 0.5, z1 as f32 + 0.5];
+    let state = cells[0].1;
+    let color = world.rule.appearance(state).color;
+
+    for d in 0..3 {
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+        for (plane, sign) in [(hi[d], 1), (lo[d], -1)] {
+            let corner = |cu: f32, cv: f32| {
+                let mut c = [0.0; 3];
+                c[d] = plane;
+                c[u] = cu;
+                c[v] = cv;
+                c
+            };
+            let mut corners = [
+                corner(lo[u], lo[v]),
+                corner(hi[u], lo[v]),
+                corner(hi[u], hi[v]),
+                corner(lo[u], hi[v]),
+            ];
+            if sign < 0 {
+                corners.reverse();
+            }
+            let mut normal = [0.0; 3];
+            normal[d] = sign as f32;
+            mesh.quad(corners, normal, color);
+        }
+    }
+
+    mesh
+}
+
+/// Greedy meshes a single chunk.
+pub fn mesh_chunk<A: Automaton>(world: &World<A>, chunk: Pos) -> ChunkMesh {
+    let origin = [chunk.0 * CHUNK, chunk.1 * CHUNK, chunk.2 * CHUNK];
+    let side = Block::<A::State>::SIDE;
+    let mut block = Block {
+        cells: vec![A::State::default(); (side * side * side) as usize],
+    };
+    let min = (origin[0] - 1, origin[1] - 1, origin[2] - 1);
+    let max = (origin[0] + CHUNK, origin[1] + CHUNK, origin[2] + CHUNK);
+    let mut any = false;
+    for (p, state) in world.cells_in(min, max) {
+        let i = (p.0 - min.0) + (p.1 - min.1) * side + (p.2 - min.2) * side * side;
+        block.cells[i as usize] = state;
+        any |= chunk_of(p) == chunk;
+    }
+
+    let mut mesh = ChunkMesh::default();
+    if !any {
+        return mesh;
+    }
+
+    let quiet = A::State::default();
+    let n = CHUNK as usize;
+    let mut mask: Vec<Option<A::State>> = vec![None; n * n];
+    for d in 0..3 {
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+        for sign in [1, -1] {
+            for slice in 0..CHUNK {
+                // faces of this slice that look out at a dead cell
+                for b in 0..CHUNK {
+                    for a in 0..CHUNK {
+                        let mut p = [0; 3];
+                        p[d] = slice;
+                        p[u] = a;
+                        p[v] = b;
+                        let state = block.get(p);
+                        p[d] += sign;
+                        mask[(a + b * CHUNK) as usize] =
+                            (state != quiet && block.get(p) == quiet).then_some(state);
+                    }
+                }
+
+                // merge runs along u, then stretch them along v
+                for b in 0..n {
+                    let mut a = 0;
+                    while a < n {
+                        let Some(state) = mask[a + b * n] else {
+                            a += 1;
+                            continue;
+                        };
+                        let mut w = 1;
+                        while a + w < n && mask[a + w + b * n] == Some(state) {
+                            w += 1;
+                        }
+                        let mut h = 1;
+                        while b + h < n && (a..a + w).all(|x| mask[x + (b + h) * n] == Some(state))
+                        {
+                            h += 1;
+                        }
+                        for y in b..b + h {
+                            mask[a + y * n..a + w + y * n].fill(None);
+                        }
+
+                        let plane = (origin[d] + slice) as f32 + 0.5 * sign as f32;
+                        let (u0, v0) = (
+                            (origin[u] + a as i32) as f32 - 0.5,
+                            (origin[v] + b as i32) as f32 - 0.5,
+                        );
+                        let (u1, v1) = (u0 + w as f32, v0 + h as f32);
+                        let corner = |cu: f32, cv: f32| {
+                            let mut c = [0.0; 3];
+                            c[d] = plane;
+                            c[u] = cu;
+                            c[v] = cv;
+                            c
+                        };
+                        // counterclockwise seen from outside the cell
+                        let mut corners = [
+                            corner(u0, v0),
+                            corner(u1, v0),
+                            corner(u1, v1),
+                            corner(u0, v1),
+                        ];
+                        if sign < 0 {
+                            corners.reverse();
+                        }
+                        let mut normal = [0.0; 3];
+                        normal[d] = sign as f32;
+                        mesh.quad(corners, normal, world.rule.appearance(state).color);
+
+                        a += w;
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::rule::Rule;
+    use crate::game::Game;
+
+    // the area of each quad in a mesh, they're all rectangles
+    fn quad_areas(mesh: &ChunkMesh) -> Vec<f32> {
+        let vertex = |i: usize| {
+            let v = &mesh.vertices[i * ChunkMesh::VERTEX_LEN..];
+            na::Point3::new(v[0], v[1], v[2])
+        };
+        (0..mesh.vertices.len() / ChunkMesh::VERTEX_LEN / 4)
+            .map(|q| {
+                let c = vertex(q * 4);
+                (vertex(q * 4 + 1) - c).norm() * (vertex(q * 4 + 3) - c).norm()
+            })
+            .collect()
+    }
+
+    fn cube(min: Pos, side: i32) -> Vec<Pos> {
+        let mut cells = vec![];
+        for x in 0..side {
+            for y in 0..side {
+                for z in 0..side {
+                    cells.push((min.0 + x, min.1 + y, min.2 + z));
+                }
+            }
+        }
+        cells
+    }
+
+    // every quad of every chunk with something in it
+    fn remeshed(game: &Game) -> Vec<(Pos, Vec<f32>)> {
+        let mut chunks = Chunks::from_world(game);
+        let mut out: Vec<(Pos, Vec<f32>)> = chunks
+            .remesh(game)
+            .into_iter()
+            .filter_map(|c| Some((c, quad_areas(chunks.get(c)?))))
+            .collect();
+        out.sort_by_key(|(c, _)| *c);
+        out
+    }
+
+    #[test]
+    fn merges_a_block_into_six_quads() {
+        let game = Game::with_cells(cube((3, 3, 3), 2), Rule::LIFE_4555);
+        let mesh = mesh_chunk(&game, (0, 0, 0));
+        assert_eq!(quad_areas(&mesh), [4.0; 6]);
+        assert_eq!(mesh.indices.len(), 6 * 6);
+    }
+
+    #[test]
+    fn covers_every_exposed_face() {
+        let mut seed = 0x9e37_79b9u32;
+        let cells: Vec<Pos> = (0..3000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let c = |shift: u32| ((seed >> shift) % 40) as i32 - 20;
+                (c(0), c(8), c(16))
+            })
+            .collect();
+        let game = Game::with_cells(cells, Rule::LIFE_4555);
+
+        let exposed: usize = game
+            .cells()
+            .map(|(p, _)| {
+                [
+                    (p.0 - 1, p.1, p.2),
+                    (p.0 + 1, p.1, p.2),
+                    (p.0, p.1 - 1, p.2),
+                    (p.0, p.1 + 1, p.2),
+                    (p.0, p.1, p.2 - 1),
+                    (p.0, p.1, p.2 + 1),
+                ]
+                .into_iter()
+                .filter(|n| !game.get(*n))
+                .count()
+            })
+            .sum();
+        let area: f32 = remeshed(&game).iter().flat_map(|(_, a)| a).sum();
+        assert_eq!(area as usize, exposed);
+    }
+
+    #[test]
+    fn splits_a_block_across_chunks() {
+        // x = 15 and 16 sit either side of the boundary between two chunks
+        let mut game = Game::with_cells(cube((15, 0, 0), 2), Rule::LIFE_4555);
+        let meshes = remeshed(&game);
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].0, (0, 0, 0));
+        assert_eq!(meshes[1].0, (1, 0, 0));
+        // no faces where the halves meet
+        for (_, areas) in meshes.iter() {
+            assert_eq!(areas.len(), 5);
+            assert_eq!(areas.iter().sum::<f32>(), 12.0);
+        }
+
+        // changing one side leaves the other's shared face to fix up
+        let mut chunks = Chunks::from_world(&game);
+        chunks.remesh(&game);
+        let diff = game.edit(cube((16, 0, 0), 2).into_iter().map(|c| (c, false)));
+        chunks.mark(&diff);
+        assert!(chunks.remesh(&game).contains(&(0, 0, 0)));
+        assert_eq!(quad_areas(chunks.get((0, 0, 0)).unwrap()).len(), 6);
+        assert!(chunks.get((1, 0, 0)).is_none());
+    }
+}